@@ -0,0 +1,142 @@
+/*
+Copyright 2022 Marek Suchánek <msuchane@redhat.com>
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Read the files inside tar and zip archives without extracting them to disk.
+
+use std::ffi::OsString;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use color_eyre::eyre::WrapErr;
+use color_eyre::Result;
+use flate2::read::GzDecoder;
+
+/// The separator between the path of the archive and the path of a file inside it.
+const SEPARATOR: &str = "!/";
+
+/// The supported archive formats.
+#[derive(Clone, Copy, Debug)]
+pub enum Archive {
+    Tar,
+    TarGz,
+    Zip,
+}
+
+impl Archive {
+    /// Recognize the archive format by the file name, if it's an archive.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        const SUFFIXES: [(&str, Archive); 4] = [
+            (".tar", Archive::Tar),
+            (".tar.gz", Archive::TarGz),
+            (".tgz", Archive::TarGz),
+            (".zip", Archive::Zip),
+        ];
+
+        let name = path.file_name()?.to_string_lossy().to_lowercase();
+
+        SUFFIXES
+            .iter()
+            .find(|(suffix, _)| name.ends_with(suffix))
+            .map(|(_, archive)| *archive)
+    }
+
+    /// Pass each regular file in the archive to the function, with its path
+    /// inside the archive, its size, and a reader of its content.
+    pub fn for_each_file<F>(self, path: &Path, mut visit: F) -> Result<()>
+    where
+        F: FnMut(&Path, u64, &mut dyn Read) -> Result<()>,
+    {
+        let error = || format!("Failed to read the archive: {}", path.display());
+        let file = fs::File::open(path).wrap_err_with(error)?;
+
+        match self {
+            Self::Tar => for_each_tar_file(file, visit).wrap_err_with(error),
+            Self::TarGz => for_each_tar_file(GzDecoder::new(file), visit).wrap_err_with(error),
+            Self::Zip => {
+                let mut zip = zip::ZipArchive::new(file).wrap_err_with(error)?;
+
+                for index in 0..zip.len() {
+                    let mut entry = zip.by_index(index).wrap_err_with(error)?;
+                    if !entry.is_file() {
+                        continue;
+                    }
+                    // Skip the entries with unsafe paths, such as those with `..`.
+                    let Some(inner) = entry.enclosed_name() else {
+                        log::warn!("Skipping an invalid path in the archive: {}", entry.name());
+                        continue;
+                    };
+                    let size = entry.size();
+                    visit(&inner, size, &mut entry)?;
+                }
+
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Pass each regular file in the tar archive to the function.
+fn for_each_tar_file<R, F>(reader: R, mut visit: F) -> Result<()>
+where
+    R: Read,
+    F: FnMut(&Path, u64, &mut dyn Read) -> Result<()>,
+{
+    let mut tar = tar::Archive::new(reader);
+
+    for entry in tar.entries()? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let inner = entry.path()?.into_owned();
+        let size = entry.size();
+        visit(&inner, size, &mut entry)?;
+    }
+
+    Ok(())
+}
+
+/// The virtual path of a file inside an archive, such as `drop.tar.gz!/guide/intro.adoc`.
+pub fn virtual_path(archive: &Path, inner: &Path) -> PathBuf {
+    let mut path = OsString::from(archive);
+    path.push(SEPARATOR);
+    path.push(inner);
+    PathBuf::from(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn archive_formats() {
+        assert!(matches!(
+            Archive::from_path(Path::new("drop.TAR.GZ")),
+            Some(Archive::TarGz)
+        ));
+        assert!(matches!(
+            Archive::from_path(Path::new("drop.zip")),
+            Some(Archive::Zip)
+        ));
+        assert!(Archive::from_path(Path::new("guide.adoc")).is_none());
+
+        assert_eq!(
+            PathBuf::from("docs/drop.tar.gz!/guide/intro.adoc"),
+            virtual_path(Path::new("docs/drop.tar.gz"), Path::new("guide/intro.adoc"))
+        );
+    }
+}
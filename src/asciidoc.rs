@@ -0,0 +1,289 @@
+/*
+Copyright 2022 Marek Suchánek <msuchane@redhat.com>
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Preprocess AsciiDoc files, so that the comparison sees the content
+//! rather than comments, directives, and attribute references.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use color_eyre::eyre::WrapErr;
+use color_eyre::Result;
+use regex::{Captures, Regex};
+
+use crate::blocks::blocked_lines;
+use crate::cli::Block;
+use crate::Options;
+
+/// The deepest nesting of included files, as a safeguard against include cycles.
+const MAX_INCLUDE_DEPTH: usize = 64;
+
+/// The AsciiDoc preprocessor, with the attributes from the `--asciidoc-attributes` file.
+pub struct AsciiDoc<'a> {
+    options: &'a Options,
+    attributes: BTreeMap<String, String>,
+    comment_block: Block,
+    /// An attribute entry, such as `:product: Example`.
+    attribute_entry: Regex,
+    /// An attribute reference, such as `{product}`.
+    reference: Regex,
+    include: Regex,
+    /// A line with only a block anchor or ID, such as `[[intro]]` or `[id="intro"]`.
+    anchor_line: Regex,
+    /// An inline anchor, such as `[[intro]]`.
+    inline_anchor: Regex,
+    /// A preprocessor conditional without inline content, such as `ifdef::attr[]`.
+    conditional: Regex,
+    /// The delimiter of a listing or literal block, in which `//` isn't a comment.
+    verbatim_delimiter: Regex,
+}
+
+impl<'a> AsciiDoc<'a> {
+    pub fn new(options: &'a Options) -> Result<Self> {
+        let mut asciidoc = Self {
+            options,
+            attributes: BTreeMap::new(),
+            comment_block: "asciidoc-comment"
+                .parse()
+                .map_err(color_eyre::eyre::Report::msg)?,
+            attribute_entry: Regex::new(r"^:(\w[\w-]*):(?:\s+(.*?))?\s*$")?,
+            reference: Regex::new(r"\{(\w[\w-]*)\}")?,
+            include: Regex::new(r"^include::([^\[]+)\[[^\]]*\]\s*$")?,
+            anchor_line: Regex::new(r"^\s*(\[\[[^\]]*\]\]|\[(#|id=)[^\]]*\])\s*$")?,
+            inline_anchor: Regex::new(r"\[\[[^\]]*\]\]")?,
+            conditional: Regex::new(r"^((ifdef|ifndef|endif)::[^\[]*\[\]|ifeval::\[.*\])\s*$")?,
+            verbatim_delimiter: Regex::new(r"^(-{4,}|\.{4,})\s*$")?,
+        };
+
+        if let Some(file) = &options.asciidoc_attributes {
+            let text = fs::read_to_string(file).wrap_err_with(|| {
+                format!("Failed to read the attributes file: {}", file.display())
+            })?;
+            for line in text.lines() {
+                if let Some((name, value)) = asciidoc.attribute_entry(line) {
+                    asciidoc.attributes.insert(name, value);
+                }
+            }
+        }
+
+        Ok(asciidoc)
+    }
+
+    /// The indexes of the lines that the comparison skips: comments, and optionally
+    /// anchors and conditionals.
+    pub fn skipped_lines(&self, text: &str) -> Vec<usize> {
+        let mut skipped: BTreeSet<usize> =
+            blocked_lines(text, std::slice::from_ref(&self.comment_block))
+                .into_iter()
+                .collect();
+        let mut verbatim = false;
+
+        for (index, line) in text.lines().enumerate() {
+            if self.verbatim_delimiter.is_match(line) {
+                verbatim = !verbatim;
+            }
+
+            let skip = (!verbatim && is_comment_line(line))
+                || (self.options.asciidoc_ignore_anchors && self.anchor_line.is_match(line))
+                || (self.options.asciidoc_ignore_conditionals && self.conditional.is_match(line));
+
+            if skip {
+                skipped.insert(index);
+            }
+        }
+
+        skipped.into_iter().collect()
+    }
+
+    /// Substitute the attribute references, resolve the includes with `--asciidoc-includes`,
+    /// and remove the inline anchors with `--asciidoc-ignore-anchors`.
+    pub fn expand(&self, text: &str, path: &Path) -> String {
+        let mut attributes = self.attributes.clone();
+        let mut stack = vec![path.canonicalize().unwrap_or_else(|_| path.to_path_buf())];
+        let mut output = Vec::new();
+
+        self.expand_lines(text, path, &mut attributes, &mut stack, &mut output);
+
+        output.join("\n")
+    }
+
+    /// Expand the lines of a file into the output. The stack lists the file
+    /// and the files that include it.
+    fn expand_lines(
+        &self,
+        text: &str,
+        path: &Path,
+        attributes: &mut BTreeMap<String, String>,
+        stack: &mut Vec<PathBuf>,
+        output: &mut Vec<String>,
+    ) {
+        for line in text.lines() {
+            // The entry applies to the rest of the document.
+            if let Some((name, value)) = self.attribute_entry(line) {
+                let value = self.substitute(&value, attributes);
+                attributes.insert(name, value);
+            }
+
+            let line = self.substitute(line, attributes);
+
+            if self.options.asciidoc_includes {
+                if let Some(target) = self
+                    .include
+                    .captures(&line)
+                    .map(|captures| captures[1].to_string())
+                {
+                    let included = path.parent().unwrap_or(Path::new("")).join(target.trim());
+                    if self.include_file(&included, attributes, stack, output) {
+                        continue;
+                    }
+                }
+            }
+
+            let line = if self.options.asciidoc_ignore_anchors {
+                self.inline_anchor.replace_all(&line, "").into_owned()
+            } else {
+                line
+            };
+
+            output.push(line);
+        }
+    }
+
+    /// Expand the included file into the output, without its skipped lines.
+    /// Returns `false` if the file can't be included, so that the directive stays.
+    fn include_file(
+        &self,
+        path: &Path,
+        attributes: &mut BTreeMap<String, String>,
+        stack: &mut Vec<PathBuf>,
+        output: &mut Vec<String>,
+    ) -> bool {
+        let canonical = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+
+        if stack.len() > MAX_INCLUDE_DEPTH || stack.contains(&canonical) {
+            log::warn!("Not resolving a recursive include: {}", path.display());
+            return false;
+        }
+
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(error) => {
+                log::warn!("Failed to resolve the include: {}: {error}", path.display());
+                return false;
+            }
+        };
+
+        let skipped = self.skipped_lines(&text);
+        let kept = text
+            .lines()
+            .enumerate()
+            .filter(|(index, _)| skipped.binary_search(index).is_err())
+            .map(|(_, line)| line)
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        stack.push(canonical);
+        self.expand_lines(&kept, path, attributes, stack, output);
+        stack.pop();
+
+        true
+    }
+
+    /// Parse the attribute entry on the line, if any, as its name and value.
+    fn attribute_entry(&self, line: &str) -> Option<(String, String)> {
+        self.attribute_entry.captures(line).map(|captures| {
+            let value = captures.get(2).map_or("", |value| value.as_str());
+            (captures[1].to_string(), value.to_string())
+        })
+    }
+
+    /// Replace the references to the known attributes with their values.
+    /// The unknown references stay as they are.
+    fn substitute(&self, line: &str, attributes: &BTreeMap<String, String>) -> String {
+        self.reference
+            .replace_all(line, |captures: &Captures| {
+                attributes
+                    .get(&captures[1])
+                    .cloned()
+                    .unwrap_or_else(|| captures[0].to_string())
+            })
+            .into_owned()
+    }
+}
+
+/// Check whether the line is a single-line comment. Lines of four or more slashes
+/// delimit comment blocks instead.
+fn is_comment_line(line: &str) -> bool {
+    let line = line.trim_end();
+    let delimiter = line.len() >= 4 && line.chars().all(|character| character == '/');
+
+    line.starts_with("//") && !delimiter
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+    use crate::Cli;
+
+    #[test]
+    fn preprocessed_asciidoc() {
+        let dir = crate::test_dir(
+            "asciidoc",
+            &[(
+                "snippet.adoc",
+                b"// A comment in the snippet.\nShared text about {product}.\n",
+            )],
+        );
+
+        let options = Options::from(&Cli::parse_from([
+            "near-facsimile",
+            "--asciidoc-includes",
+            "--asciidoc-ignore-anchors",
+            "--asciidoc-ignore-conditionals",
+        ]));
+        let asciidoc = AsciiDoc::new(&options).unwrap();
+
+        let text = ":product: Example\n\
+            [id=\"intro_{context}\"]\n\
+            // A comment.\n\
+            ////\n\
+            A comment block.\n\
+            ////\n\
+            ifdef::upstream[]\n\
+            Welcome to {product} [[welcome]]and {unknown}.\n\
+            endif::[]\n\
+            include::snippet.adoc[]\n\
+            ----\n\
+            // Code, not a comment.\n\
+            ----";
+
+        assert_eq!(vec![1, 2, 3, 4, 5, 6, 8], asciidoc.skipped_lines(text));
+
+        let kept = ":product: Example\n\
+            Welcome to {product} [[welcome]]and {unknown}.\n\
+            include::snippet.adoc[]";
+        let expanded = ":product: Example\n\
+            Welcome to Example and {unknown}.\n\
+            Shared text about Example.";
+
+        assert_eq!(expanded, asciidoc.expand(kept, &dir.join("assembly.adoc")));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,127 @@
+/*
+Copyright 2022 Marek Suchánek <msuchane@redhat.com>
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Find the blocks of lines that the `--skip-block` option skips.
+
+use std::collections::BTreeSet;
+
+use crate::cli::Block;
+
+/// The indexes of the lines that belong to any of the blocks, in ascending order.
+pub fn blocked_lines(text: &str, blocks: &[Block]) -> Vec<usize> {
+    let lines: Vec<&str> = text.lines().collect();
+
+    blocks
+        .iter()
+        .flat_map(|block| block_lines(&lines, block))
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect()
+}
+
+/// The indexes of the lines in the blocks of this kind, including the delimiters.
+///
+/// If the start and end delimiters differ, the blocks can nest, and a block only ends
+/// with the end delimiter of the outermost block. If the delimiters are the same,
+/// such as `////`, each delimiter either opens or closes a block. A block that never
+/// ends isn't skipped, because it's more likely a stray delimiter than a block.
+fn block_lines(lines: &[&str], block: &Block) -> Vec<usize> {
+    let symmetric = block.start.as_str() == block.end.as_str();
+    let mut blocked = Vec::new();
+    // The lines of the block that's still open.
+    let mut open = Vec::new();
+    let mut depth = 0_usize;
+
+    for (index, line) in lines.iter().enumerate() {
+        if depth == 0 {
+            let starts = block.start.find_iter(line).count();
+            if starts == 0 || (block.at_start && index > 0) {
+                continue;
+            }
+            depth = if symmetric { 1 } else { starts };
+        } else if symmetric {
+            if block.end.is_match(line) {
+                depth = 0;
+            }
+        } else {
+            depth += block.start.find_iter(line).count();
+        }
+
+        open.push(index);
+
+        // The closing delimiter can be on the same line as the opening one.
+        if !symmetric {
+            depth = depth.saturating_sub(block.end.find_iter(line).count());
+        }
+
+        if depth == 0 {
+            blocked.append(&mut open);
+        }
+    }
+
+    if !open.is_empty() {
+        log::debug!(
+            "Not skipping a block that doesn't end: {}",
+            block.definition
+        );
+    }
+
+    blocked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nested_and_symmetric_blocks() {
+        let blocks: Vec<Block> = ["front-matter", "asciidoc-comment", "c-comment"]
+            .iter()
+            .map(|preset| preset.parse().unwrap())
+            .collect();
+        let html: Block = "html-comment".parse().unwrap();
+
+        let text = "---\n\
+            title: Intro\n\
+            ---\n\
+            Text /* an inline comment */ here.\n\
+            /* An aside. */ More text.\n\
+            ////\n\
+            Commented out.\n\
+            ////\n\
+            /* outer\n\
+            /* inner */\n\
+            still outer */\n\
+            ---\n\
+            ////\n\
+            Stray delimiter.";
+
+        // The lines with the inline comments stay, with their text.
+        assert_eq!(
+            vec![0, 1, 2, 5, 6, 7, 8, 9, 10],
+            blocked_lines(text, &blocks)
+        );
+
+        let text = "Intro.\n\
+            <!-- A note. --> Install the package.\n\
+            <!--\n\
+            A draft.\n\
+            -->\n\
+            <!-- One line. -->\n\
+            Outro.";
+        assert_eq!(vec![2, 3, 4, 5], blocked_lines(text, &[html]));
+    }
+}
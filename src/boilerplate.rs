@@ -0,0 +1,254 @@
+/*
+Copyright 2022 Marek Suchánek <msuchane@redhat.com>
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Find the lines and paragraphs that many files share, such as copyright notices
+//! or attribute headers, and optionally remove them before the comparison.
+
+use std::collections::{BTreeMap, HashSet};
+use std::ops::Range;
+
+use crate::{File, Options};
+
+/// A line or a paragraph that appears in many files.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Boilerplate {
+    /// The trimmed lines of the paragraph, or a single line.
+    pub lines: Vec<String>,
+    /// The number of files that contain it.
+    pub files: usize,
+}
+
+/// Find the boilerplate in more than the `--boilerplate` percentage of files,
+/// and remove it from the compared content with `--strip-boilerplate`.
+pub fn handle_boilerplate(files: &mut [File], options: &Options) -> Vec<Boilerplate> {
+    let Some(percentage) = options.boilerplate else {
+        return Vec::new();
+    };
+
+    let found = find(files, percentage);
+
+    if options.strip_boilerplate && !found.is_empty() {
+        strip(files, &found);
+    }
+
+    found
+}
+
+/// The lines and paragraphs of several lines that appear in more than the percentage
+/// of files, and in at least two files, from the most common. The lines and paragraphs
+/// without any letters or digits, such as delimiters, don't count. The lines of a reported
+/// paragraph only appear on their own if more files contain them.
+// The file counts are far below the precision limit of `f64`.
+#[allow(clippy::cast_precision_loss)]
+pub fn find(files: &[File], percentage: f64) -> Vec<Boilerplate> {
+    let mut counts: BTreeMap<Vec<String>, usize> = BTreeMap::new();
+
+    for file in files {
+        let lines = trimmed_lines(&file.content);
+        let mut items: HashSet<Vec<String>> = HashSet::new();
+
+        for range in paragraphs(&lines) {
+            if range.len() > 1 {
+                items.insert(lines[range.clone()].to_vec());
+            }
+            items.extend(range.map(|index| vec![lines[index].clone()]));
+        }
+
+        for item in items {
+            *counts.entry(item).or_insert(0) += 1;
+        }
+    }
+
+    // The number of files that the boilerplate must exceed.
+    let minimum = files.len() as f64 * percentage / 100.0;
+
+    let mut found: Vec<Boilerplate> = counts
+        .into_iter()
+        .filter(|(lines, count)| {
+            *count >= 2
+                && *count as f64 > minimum
+                && lines
+                    .iter()
+                    .flat_map(|line| line.chars())
+                    .any(char::is_alphanumeric)
+        })
+        .map(|(lines, files)| Boilerplate { lines, files })
+        .collect();
+
+    // A line that only occurs in a reported paragraph would repeat it in the report.
+    let in_paragraphs: HashSet<(String, usize)> = found
+        .iter()
+        .filter(|item| item.lines.len() > 1)
+        .flat_map(|item| item.lines.iter().map(|line| (line.clone(), item.files)))
+        .collect();
+    found.retain(|item| {
+        item.lines.len() > 1 || !in_paragraphs.contains(&(item.lines[0].clone(), item.files))
+    });
+
+    found.sort_by(|a, b| b.files.cmp(&a.files).then_with(|| a.lines.cmp(&b.lines)));
+
+    found
+}
+
+/// Remove the boilerplate paragraphs and lines from the compared content.
+/// The removed lines count as skipped, so that the diffs list them.
+fn strip(files: &mut [File], found: &[Boilerplate]) {
+    let whole_paragraphs: HashSet<&[String]> = found
+        .iter()
+        .filter(|item| item.lines.len() > 1)
+        .map(|item| item.lines.as_slice())
+        .collect();
+    let single_lines: HashSet<&str> = found
+        .iter()
+        .filter(|item| item.lines.len() == 1)
+        .map(|item| item.lines[0].as_str())
+        .collect();
+
+    for file in files {
+        let lines = trimmed_lines(&file.content);
+        let mut removed = vec![false; lines.len()];
+
+        for range in paragraphs(&lines) {
+            if whole_paragraphs.contains(&lines[range.clone()]) {
+                removed[range].fill(true);
+            }
+        }
+        for (index, line) in lines.iter().enumerate() {
+            if single_lines.contains(line.as_str()) {
+                removed[index] = true;
+            }
+        }
+
+        let removed_lines: Vec<usize> = (0..lines.len()).filter(|&index| removed[index]).collect();
+        if removed_lines.is_empty() {
+            continue;
+        }
+
+        let content = file
+            .content
+            .lines()
+            .zip(&removed)
+            .filter(|(_, &removed)| !removed)
+            .map(|(line, _)| line)
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        file.remove_lines(content, &removed_lines);
+    }
+}
+
+fn trimmed_lines(text: &str) -> Vec<String> {
+    text.lines().map(|line| line.trim().to_string()).collect()
+}
+
+/// The ranges of the paragraphs, which are separated by blank lines.
+fn paragraphs(lines: &[String]) -> Vec<Range<usize>> {
+    let mut ranges = Vec::new();
+    let mut start = None;
+
+    for (index, line) in lines.iter().enumerate() {
+        match (line.is_empty(), start) {
+            (false, None) => start = Some(index),
+            (true, Some(first)) => {
+                ranges.push(first..index);
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(first) = start {
+        ranges.push(first..lines.len());
+    }
+
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(content: &str) -> File {
+        File::with_content("", content)
+    }
+
+    #[test]
+    fn found_and_stripped_boilerplate() {
+        let header = "Copyright Example\nAll rights reserved.\n\n";
+        let mut files = vec![
+            file(&format!("{header}----\nFirst topic.")),
+            file(&format!("{header}----\nSecond topic.")),
+            file(&format!("{header}Third topic.\nAll rights reserved.")),
+            file("Fourth topic."),
+        ];
+
+        let found = find(&files, 50.0);
+        let items: Vec<(String, usize)> = found
+            .iter()
+            .map(|item| (item.lines.join("\n"), item.files))
+            .collect();
+        // The lines of the paragraph only occur in it, so they aren't reported again.
+        assert_eq!(
+            vec![("Copyright Example\nAll rights reserved.".to_string(), 3)],
+            items
+        );
+
+        strip(&mut files, &found);
+        assert_eq!("\n----\nFirst topic.", files[0].content);
+        assert_eq!(vec![0, 1], files[0].skipped);
+        assert_eq!("\nThird topic.\nAll rights reserved.", files[2].content);
+        assert_eq!(vec![0, 1], files[2].skipped);
+
+        assert_eq!("Fourth topic.", files[3].content);
+
+        // A line that also occurs outside the paragraph in more files is reported.
+        let files = [
+            file(&format!("{header}First topic.")),
+            file(&format!("{header}Second topic.")),
+            file("Third topic.\nAll rights reserved."),
+        ];
+        let items: Vec<(String, usize)> = find(&files, 50.0)
+            .iter()
+            .map(|item| (item.lines.join("\n"), item.files))
+            .collect();
+        assert_eq!(
+            vec![
+                ("All rights reserved.".to_string(), 3),
+                ("Copyright Example\nAll rights reserved.".to_string(), 2),
+            ],
+            items
+        );
+    }
+
+    #[test]
+    fn skipped_by_position() {
+        let header = "Copyright Example\nAll rights reserved.\n\n";
+        // The first file quotes the boilerplate in a code block, which the compared
+        // prose doesn't contain.
+        let mut quoted = file(&format!("{header}Topic.\n```\nAll rights reserved.\n```"));
+        quoted.set_content(format!("{header}Topic."));
+        let mut files = vec![
+            quoted,
+            file(&format!("{header}Other topic.")),
+            file(&format!("{header}Another topic.")),
+        ];
+
+        let found = find(&files, 50.0);
+        strip(&mut files, &found);
+
+        assert_eq!("\nTopic.", files[0].content);
+        assert_eq!(vec![0, 1], files[0].skipped);
+    }
+}
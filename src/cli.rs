@@ -15,17 +15,23 @@ limitations under the License.
 */
 
 use std::ffi::OsString;
-use std::path::PathBuf;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
-use clap::Parser;
+use clap::parser::ValueSource;
+use clap::{CommandFactory, FromArgMatches, Parser};
 use regex::Regex;
 
+// The command-line flags are naturally booleans.
+#[allow(clippy::struct_excessive_bools)]
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 pub struct Cli {
-    /// Path to the root documentation directory
+    /// Path to the root documentation directory. Repeat the option to compare
+    /// files across several directories
     #[arg(short, long, value_parser, value_name = "DIR", default_value = ".")]
-    pub path: PathBuf,
+    pub path: Vec<PathBuf>,
 
     /// The similarity percentage above which to report files
     #[arg(short, long, value_name = "DECIMAL", default_value = "85.0")]
@@ -35,6 +41,11 @@ pub struct Cli {
     #[arg(short, long, action = clap::ArgAction::Count)]
     pub fast: u8,
 
+    /// Compare the files with this metric: `levenshtein`, `jaro`, `trigram`, or `tfidf`,
+    /// which discounts the words that most files share. Overrides `--fast`
+    #[arg(long, value_name = "NAME")]
+    pub metric: Option<Metric>,
+
     /// Display status and debugging information
     #[arg(short, long, action = clap::ArgAction::Count)]
     pub verbose: u8,
@@ -47,35 +58,705 @@ pub struct Cli {
     #[arg(short, long, value_name = "FILE")]
     pub json: Option<PathBuf>,
 
+    /// Save a unified diff of each similar pair in this directory
+    #[arg(long, value_name = "DIR")]
+    pub diff_dir: Option<PathBuf>,
+
+    /// Include file sizes, scores, and line changes in the CSV and JSON results
+    #[arg(short = 'D', long, action)]
+    pub details: bool,
+
+    /// Save the results as a Markdown summary
+    #[arg(short, long, value_name = "FILE")]
+    pub markdown: Option<PathBuf>,
+
+    /// Save the results as a JUnit XML report
+    #[arg(long, value_name = "FILE")]
+    pub junit: Option<PathBuf>,
+
     /// Ignore this file name in the search and comparison
-    #[arg(long, value_name = "NAME", conflicts_with = "require_file")]
+    #[arg(long, value_name = "NAME")]
     pub ignore_file: Vec<OsString>,
 
     /// Ignore this file extension in the search and comparison
-    #[arg(long, value_name = "EXTENSION", conflicts_with = "require_ext")]
+    #[arg(long, value_name = "EXTENSION")]
     pub ignore_ext: Vec<OsString>,
 
     /// Look for this file name in the search and comparison
-    #[arg(long, value_name = "NAME", conflicts_with = "ignore_ext")]
+    #[arg(long, value_name = "NAME")]
     pub require_file: Vec<OsString>,
 
     /// Look for this file extension in the search and comparison
     #[arg(long, value_name = "EXTENSION")]
     pub require_ext: Vec<OsString>,
 
+    /// Compare the files listed in this file, or in the standard input if `-`,
+    /// rather than searching the directories. The paths are separated by new lines
+    /// or by NUL characters
+    #[arg(long, value_name = "FILE")]
+    pub files_from: Option<PathBuf>,
+
+    /// Look for the files that match this glob pattern in the gitignore syntax
+    #[arg(long, value_name = "GLOB")]
+    pub include: Vec<String>,
+
+    /// Ignore the files that match this glob pattern in the gitignore syntax.
+    /// Takes precedence over all inclusions
+    #[arg(long, value_name = "GLOB")]
+    pub exclude: Vec<String>,
+
+    /// Also search hidden files and directories
+    #[arg(long, action)]
+    pub hidden: bool,
+
+    /// Also search the files that `.gitignore`, `.ignore`, and similar files exclude.
+    /// The `.nfignore` files still apply
+    #[arg(long, action)]
+    pub no_ignore: bool,
+
+    /// Follow symbolic links when searching the directories
+    #[arg(long, action)]
+    pub follow_symlinks: bool,
+
+    /// Search at most this many levels of directories below each root directory
+    #[arg(long, value_name = "NUMBER")]
+    pub max_depth: Option<usize>,
+
+    /// Also compare the files inside tar, tar.gz, and zip archives, without extracting them
+    #[arg(long, action)]
+    pub archives: bool,
+
+    /// The encoding of the compared files, such as `utf-8`, `latin1`, or `utf-16le`.
+    /// With `auto`, the encoding is detected in each file. A byte order mark always
+    /// determines the encoding
+    #[arg(long, value_name = "LABEL", default_value = "auto")]
+    pub encoding: String,
+
+    /// Skip files larger than this size in bytes. Use the K, M, or G suffix for
+    /// kibibytes, mebibytes, or gibibytes
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    pub max_file_size: Option<u64>,
+
+    /// Skip files smaller than this size in bytes. Use the K, M, or G suffix for
+    /// kibibytes, mebibytes, or gibibytes
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    pub min_file_size: Option<u64>,
+
+    /// Skip files with fewer compared lines than this, after skipping lines
+    #[arg(long, value_name = "NUMBER")]
+    pub min_lines: Option<usize>,
+
+    /// Skip files with fewer compared words than this, after skipping lines
+    #[arg(long, value_name = "NUMBER")]
+    pub min_words: Option<usize>,
+
+    /// List each skipped file and the reason, also in the JSON output
+    #[arg(long, action)]
+    pub list_skipped: bool,
+
+    /// List the files that would be compared, and why, without comparing them
+    #[arg(long, action)]
+    pub list_files: bool,
+
     /// Skip all lines that match this regular expression when comparing files
     #[arg(long, value_name = "REGEX")]
     pub skip_lines: Vec<Regex>,
 
+    /// How to interpret the content of the files: as `plain` text, as `asciidoc`,
+    /// which skips comments and enables the other AsciiDoc options, as `markdown`,
+    /// `html`, or `xml`, such as DocBook, which compare the text without the markup,
+    /// as `code`, which compares the tokens of Rust, Python, C, Java, or shell source code,
+    /// or `auto` to choose the format by the extension of each file
+    #[arg(long, value_name = "FORMAT", default_value = "plain")]
+    pub format: Format,
+
+    /// In the AsciiDoc format, replace the `include::` directives with the content
+    /// of the included files
+    #[arg(long, action)]
+    pub asciidoc_includes: bool,
+
+    /// In the AsciiDoc format, substitute the attributes defined in this file,
+    /// in addition to those defined in the compared files
+    #[arg(long, value_name = "FILE")]
+    pub asciidoc_attributes: Option<PathBuf>,
+
+    /// In the AsciiDoc format, skip anchors and IDs
+    #[arg(long, action)]
+    pub asciidoc_ignore_anchors: bool,
+
+    /// In the AsciiDoc format, skip the conditional directives, such as `ifdef::`,
+    /// but keep their content
+    #[arg(long, action)]
+    pub asciidoc_ignore_conditionals: bool,
+
+    /// In the Markdown format, whether to `include` the code blocks in the compared text,
+    /// `ignore` them, or compare them `separate`ly, as another file with the `#code` suffix
+    #[arg(long, value_name = "MODE", default_value = "include")]
+    pub markdown_code: CodeBlocks,
+
+    /// In the HTML and XML formats, start a new line with each block element,
+    /// such as a paragraph, rather than only where the markup does
+    #[arg(long, action)]
+    pub markup_structure: bool,
+
+    /// In the code format, replace the identifiers with a placeholder,
+    /// so that code with renamed variables and functions is identical
+    #[arg(long, action)]
+    pub code_rename_identifiers: bool,
+
+    /// In the code format, replace the string and number literals with a placeholder
+    #[arg(long, action)]
+    pub code_rename_literals: bool,
+
+    /// Compare the files as sequences of `characters`, of tokens separated by `whitespace`,
+    /// or of `words` at the Unicode word boundaries, without punctuation.
+    /// Comparing tokens is faster, and a changed word counts as a single edit
+    #[arg(long, value_name = "MODE", default_value = "characters")]
+    pub tokens: Tokens,
+
+    /// With `--tokens whitespace`, `--tokens words`, or the `tfidf` metric, leave out
+    /// the words listed in this file, one on each line, regardless of case
+    #[arg(long, value_name = "FILE")]
+    pub stopwords: Option<PathBuf>,
+
+    /// Report the lines and paragraphs that appear in more than this percentage of files,
+    /// such as copyright notices or attribute headers
+    #[arg(long, value_name = "PERCENT")]
+    pub boilerplate: Option<f64>,
+
+    /// Remove the lines and paragraphs that `--boilerplate` reports before comparing files
+    #[arg(long, action, requires = "boilerplate")]
+    pub strip_boilerplate: bool,
+
+    /// Skip the blocks of lines between a line that matches the start regular expression
+    /// and a line that matches the end one, in the `<START>..<END>` format.
+    /// Alternatively, use a preset: `front-matter`, `asciidoc-comment`, `html-comment`,
+    /// or `c-comment`
+    #[arg(long, value_name = "START..END")]
+    pub skip_block: Vec<Block>,
+
+    /// Replace the matches of the regular expression before comparing files, in the
+    /// `<REGEX>=<REPLACEMENT>` format. The replacement can refer to capture groups,
+    /// such as `$1`. Escape a `=` in the regular expression as `\=`.
+    /// Repeat the option to apply several replacements in order
+    #[arg(long, value_name = "REGEX=REPLACEMENT")]
+    pub replace: Vec<Replace>,
+
+    /// Normalize the compared content, so that formatting changes don't affect the similarity.
+    /// The kinds are `whitespace`, `unwrap` to join the lines of paragraphs, `case`,
+    /// `nfkc` for the Unicode compatibility normalization, and `punctuation` for plain
+    /// quotes and dashes. Repeat the option to apply several kinds
+    #[arg(long, value_name = "KIND")]
+    pub normalize: Vec<Normalization>,
+
+    /// Apply different settings to the files that match this glob pattern.
+    /// The format is <GLOB>:<SETTING>,..., where the settings are
+    /// threshold=<DECIMAL>, metric=<NAME>, skip-lines=<REGEX>, or ignore.
+    /// The skip-lines setting takes the rest of the rule, so it comes last
+    #[arg(long, value_name = "RULE")]
+    pub rule: Vec<Rule>,
+
     /// Display detailed progress information
     #[arg(short = 'P', long, action)]
     pub progress: bool,
+
+    /// Load the options from this configuration file, rather than from
+    /// near-facsimile.toml in the directory or its parents
+    #[arg(long, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+
+    /// Apply the options from this profile in the configuration file
+    #[arg(long, value_name = "NAME")]
+    pub profile: Option<String>,
+
+    /// The IDs of the options that the user set on the command line.
+    /// These take precedence over the configuration file.
+    #[arg(skip)]
+    pub explicit: Vec<String>,
+}
+
+/// The metric that measures the similarity of two files.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Metric {
+    Levenshtein,
+    Jaro,
+    Trigram,
+    /// The cosine similarity of the TF-IDF vectors of the words.
+    Tfidf,
+}
+
+impl Metric {
+    /// The name of the metric as presented to the user.
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Levenshtein => "levenshtein",
+            Self::Jaro => "jaro",
+            Self::Trigram => "trigram",
+            Self::Tfidf => "tfidf",
+        }
+    }
+}
+
+impl FromStr for Metric {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "levenshtein" => Ok(Self::Levenshtein),
+            "jaro" => Ok(Self::Jaro),
+            "trigram" => Ok(Self::Trigram),
+            "tfidf" => Ok(Self::Tfidf),
+            _ => Err(format!(
+                "Unknown metric `{name}`. The metrics are levenshtein, jaro, trigram, and tfidf."
+            )),
+        }
+    }
+}
+
+/// The units that the metrics compare.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Tokens {
+    Characters,
+    Whitespace,
+    Words,
+}
+
+impl Tokens {
+    /// The name of the mode as presented to the user.
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Characters => "characters",
+            Self::Whitespace => "whitespace",
+            Self::Words => "words",
+        }
+    }
+}
+
+impl FromStr for Tokens {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "characters" => Ok(Self::Characters),
+            "whitespace" => Ok(Self::Whitespace),
+            "words" => Ok(Self::Words),
+            _ => Err(format!(
+                "Unknown token mode `{name}`. The modes are characters, whitespace, and words."
+            )),
+        }
+    }
+}
+
+/// The markup language of the compared files.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Plain,
+    AsciiDoc,
+    Markdown,
+    Html,
+    Xml,
+    /// Source code in one of the supported languages.
+    Code,
+    /// Choose one of the other formats by the extension of each file.
+    Auto,
+}
+
+impl Format {
+    /// The name of the format as presented to the user.
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Plain => "plain",
+            Self::AsciiDoc => "asciidoc",
+            Self::Markdown => "markdown",
+            Self::Html => "html",
+            Self::Xml => "xml",
+            Self::Code => "code",
+            Self::Auto => "auto",
+        }
+    }
+
+    /// The format of this file. With `auto`, the format depends on the extension,
+    /// and it's plain text for unknown extensions.
+    #[must_use]
+    pub fn of_file(self, path: &Path) -> Self {
+        if self != Self::Auto {
+            return self;
+        }
+
+        let extension = path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase());
+
+        match extension.as_deref() {
+            Some("adoc" | "asciidoc" | "asc") => Self::AsciiDoc,
+            Some("md" | "markdown") => Self::Markdown,
+            Some("html" | "htm" | "xhtml") => Self::Html,
+            Some("xml" | "dbk" | "docbook") => Self::Xml,
+            _ if Language::from_path(path).is_some() => Self::Code,
+            _ => Self::Plain,
+        }
+    }
+}
+
+/// The programming language of a file in the code format.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Language {
+    Rust,
+    Python,
+    C,
+    Java,
+    Shell,
+}
+
+impl Language {
+    /// The language of the file by its extension, if it's a supported language.
+    /// C also covers C++ and the header files.
+    #[must_use]
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_string_lossy().to_lowercase();
+
+        match extension.as_str() {
+            "rs" => Some(Self::Rust),
+            "py" => Some(Self::Python),
+            "c" | "h" | "cc" | "cpp" | "cxx" | "hpp" => Some(Self::C),
+            "java" => Some(Self::Java),
+            "sh" | "bash" => Some(Self::Shell),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "plain" => Ok(Self::Plain),
+            "asciidoc" => Ok(Self::AsciiDoc),
+            "markdown" => Ok(Self::Markdown),
+            "html" => Ok(Self::Html),
+            "xml" => Ok(Self::Xml),
+            "code" => Ok(Self::Code),
+            "auto" => Ok(Self::Auto),
+            _ => Err(format!(
+                "Unknown format `{name}`. The formats are plain, asciidoc, markdown, html, xml, \
+                code, and auto."
+            )),
+        }
+    }
+}
+
+/// How the Markdown format treats the code blocks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CodeBlocks {
+    Include,
+    Ignore,
+    Separate,
+}
+
+impl FromStr for CodeBlocks {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "include" => Ok(Self::Include),
+            "ignore" => Ok(Self::Ignore),
+            "separate" => Ok(Self::Separate),
+            _ => Err(format!(
+                "Unknown code block mode `{name}`. The modes are include, ignore, and separate."
+            )),
+        }
+    }
+}
+
+/// A block of lines that the comparison skips, delimited by start and end lines.
+#[derive(Clone, Debug)]
+pub struct Block {
+    /// The definition as the user wrote it, either a preset name or `<START>..<END>`.
+    pub definition: String,
+    pub start: Regex,
+    pub end: Regex,
+    /// The block only counts if it starts on the first line of the file.
+    pub at_start: bool,
+}
+
+impl Block {
+    /// The predefined blocks: name, start, end, and whether they must start on the first line.
+    /// The comments only form a block if they start a line and end a line, because the blocks
+    /// skip whole lines, and an inline comment would take the text around it along.
+    /// The start of a comment doesn't match if the comment ends before the end of its line.
+    const PRESETS: [(&'static str, &'static str, &'static str, bool); 4] = [
+        ("front-matter", r"^---\s*$", r"^---\s*$", true),
+        ("asciidoc-comment", r"^////+\s*$", r"^////+\s*$", false),
+        (
+            "html-comment",
+            r"^\s*<!--(?:[^-]|-[^-]|--[^>])*-{0,2}(?:-->\s*)?$",
+            r"-->\s*$",
+            false,
+        ),
+        (
+            "c-comment",
+            r"^\s*/\*(?:[^*]|\*+[^*/])*\**(?:\*/\s*)?$",
+            r"\*/\s*$",
+            false,
+        ),
+    ];
+}
+
+impl FromStr for Block {
+    type Err = String;
+
+    /// Parse the block from a preset name, or from the `<START>..<END>` format,
+    /// split at the first `..`.
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let preset = Self::PRESETS.iter().find(|preset| preset.0 == text);
+        let (start, end, at_start) = if let Some(&(_, start, end, at_start)) = preset {
+            (start, end, at_start)
+        } else {
+            let (start, end) = text.split_once("..").ok_or_else(|| {
+                format!(
+                    "The block `{text}` is neither a preset nor in the `<START>..<END>` format."
+                )
+            })?;
+            (start, end, false)
+        };
+
+        Ok(Self {
+            definition: text.to_string(),
+            start: Regex::new(start).map_err(|error| error.to_string())?,
+            end: Regex::new(end).map_err(|error| error.to_string())?,
+            at_start,
+        })
+    }
+}
+
+/// A substitution in the compared content.
+#[derive(Clone, Debug)]
+pub struct Replace {
+    pub regex: Regex,
+    /// The replacement, which can refer to the capture groups of the regular expression.
+    pub replacement: String,
+}
+
+impl FromStr for Replace {
+    type Err = String;
+
+    /// Parse the substitution from the `<REGEX>=<REPLACEMENT>` format. The first `=`
+    /// that isn't escaped as `\=` separates the regular expression from the replacement.
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        // A `=` is escaped if an odd number of backslashes precedes it.
+        let escaped = |index: usize| {
+            text[..index]
+                .chars()
+                .rev()
+                .take_while(|&character| character == '\\')
+                .count()
+                % 2
+                == 1
+        };
+        let separator = text
+            .char_indices()
+            .find(|&(index, character)| character == '=' && !escaped(index))
+            .map(|(index, _)| index)
+            .ok_or_else(|| format!("The replacement `{text}` is missing `=`."))?;
+
+        Ok(Self {
+            regex: Regex::new(&text[..separator]).map_err(|error| error.to_string())?,
+            replacement: text[separator + 1..].to_string(),
+        })
+    }
+}
+
+impl fmt::Display for Replace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.regex, self.replacement)
+    }
+}
+
+/// A kind of normalization of the compared content.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Normalization {
+    Whitespace,
+    Unwrap,
+    Case,
+    Nfkc,
+    Punctuation,
+}
+
+impl Normalization {
+    /// The name of the normalization as presented to the user.
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Whitespace => "whitespace",
+            Self::Unwrap => "unwrap",
+            Self::Case => "case",
+            Self::Nfkc => "nfkc",
+            Self::Punctuation => "punctuation",
+        }
+    }
+}
+
+impl FromStr for Normalization {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "whitespace" => Ok(Self::Whitespace),
+            "unwrap" => Ok(Self::Unwrap),
+            "case" => Ok(Self::Case),
+            "nfkc" => Ok(Self::Nfkc),
+            "punctuation" => Ok(Self::Punctuation),
+            _ => Err(format!(
+                "Unknown normalization `{name}`. The normalizations are whitespace, unwrap, \
+                case, nfkc, and punctuation."
+            )),
+        }
+    }
+}
+
+/// Settings that apply to the files that match a glob pattern, rather than
+/// the global settings.
+///
+/// When a pair of compared files matches different rules, the rule
+/// with the lower threshold applies, because it reports more similar files.
+#[derive(Clone, Debug, Default)]
+pub struct Rule {
+    /// The glob pattern, matched against the path relative to the compared directory.
+    pub pattern: String,
+    /// The similarity threshold as a decimal value between 0.0 and 1.0.
+    pub threshold: Option<f64>,
+    pub metric: Option<Metric>,
+    /// Lines to skip in addition to the global `--skip-lines` option.
+    pub skip_lines: Vec<Regex>,
+    /// Leave the files out of the comparison entirely.
+    pub ignore: bool,
+}
+
+impl Rule {
+    /// Convert the threshold percentage to a decimal value between 0.0 and 1.0,
+    /// and check that it's valid.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the percentage is outside of the range between 0.0 and 100.0.
+    pub fn threshold_from_pct(percentage: f64) -> Result<f64, String> {
+        if (0.0..=100.0).contains(&percentage) {
+            Ok(percentage / 100.0)
+        } else {
+            Err("The similarity threshold must be between 0.0 and 100.0.".to_string())
+        }
+    }
+}
+
+impl FromStr for Rule {
+    type Err = String;
+
+    /// Parse the rule from the `<GLOB>:<SETTING>,...` format. The settings start
+    /// after the first `:` that a known setting follows, so the glob can contain `:`.
+    /// The regular expression of `skip-lines` extends to the end of the rule,
+    /// so that it can contain `,` and `:`.
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        const SETTINGS: [&str; 4] = ["threshold=", "metric=", "skip-lines=", "ignore"];
+
+        let (pattern, mut settings) = text
+            .match_indices(':')
+            .map(|(index, _)| (&text[..index], &text[index + 1..]))
+            .find(|(_, settings)| SETTINGS.iter().any(|name| settings.starts_with(name)))
+            .ok_or_else(|| format!("The rule `{text}` is missing the settings after `:`."))?;
+
+        let mut rule = Self {
+            pattern: pattern.to_string(),
+            ..Self::default()
+        };
+
+        while !settings.is_empty() {
+            let setting = if settings.starts_with("skip-lines=") {
+                std::mem::take(&mut settings)
+            } else {
+                let (setting, rest) = settings.split_once(',').unwrap_or((settings, ""));
+                settings = rest;
+                setting
+            };
+
+            match setting.split_once('=') {
+                Some(("threshold", value)) => {
+                    let percentage = value
+                        .parse()
+                        .map_err(|_| format!("Invalid threshold `{value}`."))?;
+                    rule.threshold = Some(Self::threshold_from_pct(percentage)?);
+                }
+                Some(("metric", value)) => rule.metric = Some(value.parse()?),
+                Some(("skip-lines", value)) => rule
+                    .skip_lines
+                    .push(Regex::new(value).map_err(|error| error.to_string())?),
+                None if setting == "ignore" => rule.ignore = true,
+                _ => return Err(format!("Unknown rule setting `{setting}`.")),
+            }
+        }
+
+        Ok(rule)
+    }
+}
+
+/// Parse a file size in bytes, with an optional K, M, or G suffix
+/// for kibibytes, mebibytes, or gibibytes.
+///
+/// # Errors
+///
+/// Fails if the size isn't a whole number with a known suffix.
+pub fn parse_size(text: &str) -> Result<u64, String> {
+    let text = text.trim();
+    let (number, multiplier) = match text.char_indices().last() {
+        Some((index, 'k' | 'K')) => (&text[..index], 1 << 10),
+        Some((index, 'm' | 'M')) => (&text[..index], 1 << 20),
+        Some((index, 'g' | 'G')) => (&text[..index], 1 << 30),
+        _ => (text, 1),
+    };
+
+    number
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .and_then(|number| number.checked_mul(multiplier))
+        .ok_or_else(|| format!("Invalid file size `{text}`."))
+}
+
+impl Cli {
+    /// Report whether the user set this option on the command line.
+    #[must_use]
+    pub fn is_explicit(&self, id: &str) -> bool {
+        self.explicit.iter().any(|explicit| explicit == id)
+    }
+
+    /// The comparison metric selected by the `--metric` option,
+    /// or by the number of `--fast` options.
+    #[must_use]
+    pub fn metric(&self) -> Metric {
+        self.metric.unwrap_or(match self.fast {
+            0 => Metric::Levenshtein,
+            1 => Metric::Jaro,
+            _ => Metric::Trigram,
+        })
+    }
 }
 
 /// Parse the current command-line options.
 #[must_use]
 pub fn options() -> Cli {
-    let mut options = Cli::parse();
+    let matches = Cli::command().get_matches();
+    let mut options = Cli::from_arg_matches(&matches).unwrap_or_else(|error| error.exit());
+
+    // Remember which options come from the command line, rather than from their defaults,
+    // so that the configuration file doesn't override them.
+    options.explicit = matches
+        .ids()
+        .filter(|id| matches.value_source(id.as_str()) == Some(ValueSource::CommandLine))
+        .map(ToString::to_string)
+        .collect();
 
     // Provide the similarity threshold as a decimal value between 0.0 and 1.0,
     // rather than the human-readable percentage between 0.0 and 100.0 that the user
@@ -87,3 +768,63 @@ pub fn options() -> Cli {
 
     options
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replace_separator() {
+        let replace: Replace = r"version \d+\.\d+=VERSION".parse().unwrap();
+        assert_eq!(r"version \d+\.\d+", replace.regex.as_str());
+        assert_eq!("VERSION", replace.replacement);
+
+        // An escaped `=` belongs to the regular expression, and the replacement can contain `=`.
+        let replace: Replace = r"id\=(\w+)=id=$1".parse().unwrap();
+        assert_eq!(r"id\=(\w+)", replace.regex.as_str());
+        assert_eq!("id=$1", replace.replacement);
+        assert_eq!("[id=x]", replace.regex.replace_all("[id=x]", "id=$1"));
+
+        assert!("no separator".parse::<Replace>().is_err());
+    }
+
+    #[test]
+    fn rule_settings() {
+        let rule: Rule = "snippets/**:threshold=70,metric=jaro,ignore"
+            .parse()
+            .unwrap();
+        assert_eq!("snippets/**", rule.pattern);
+        assert_eq!(Some(0.7), rule.threshold);
+        assert_eq!(Some(Metric::Jaro), rule.metric);
+        assert!(rule.ignore);
+
+        // The regular expression can contain the separators.
+        let rule: Rule = "*.adoc:threshold=90,skip-lines=^:[a-z]{1,3}[,;]"
+            .parse()
+            .unwrap();
+        assert_eq!(Some(0.9), rule.threshold);
+        assert_eq!("^:[a-z]{1,3}[,;]", rule.skip_lines[0].as_str());
+
+        // So can the glob pattern.
+        let rule: Rule = "C:docs/*.md:ignore".parse().unwrap();
+        assert_eq!("C:docs/*.md", rule.pattern);
+
+        assert!("*.md".parse::<Rule>().is_err());
+        assert!("*.md:threshold=120".parse::<Rule>().is_err());
+        assert!("*.md:ignore,color=red".parse::<Rule>().is_err());
+    }
+
+    #[test]
+    fn file_sizes() {
+        assert_eq!(Ok(512), parse_size("512"));
+        assert_eq!(Ok(2048), parse_size("2K"));
+        assert_eq!(Ok(3 << 20), parse_size(" 3 m "));
+        assert_eq!(Ok(1 << 30), parse_size("1G"));
+
+        assert!(parse_size("").is_err());
+        assert!(parse_size("1.5M").is_err());
+        assert!(parse_size("10T").is_err());
+        assert!(parse_size("-1").is_err());
+        assert!(parse_size("18446744073709551615G").is_err());
+    }
+}
@@ -0,0 +1,87 @@
+/*
+Copyright 2022 Marek Suchánek <msuchane@redhat.com>
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::collections::BTreeMap;
+
+/// Group files into clusters, where each file in a cluster is similar
+/// to at least one other file in the same cluster.
+///
+/// The clusters are the connected components of the graph in which files are nodes
+/// and similar pairs are edges. The largest clusters come first.
+pub fn clusters<T>(pairs: impl IntoIterator<Item = (T, T)>) -> Vec<Vec<T>>
+where
+    T: Ord + Clone,
+{
+    // A union-find structure, where each file points to its parent in the cluster tree.
+    let mut parents: BTreeMap<T, T> = BTreeMap::new();
+
+    for (file1, file2) in pairs {
+        let root1 = find_root(&mut parents, file1);
+        let root2 = find_root(&mut parents, file2);
+
+        if root1 != root2 {
+            parents.insert(root1, root2);
+        }
+    }
+
+    // Collect the files under their shared root.
+    let files: Vec<T> = parents.keys().cloned().collect();
+    let mut components: BTreeMap<T, Vec<T>> = BTreeMap::new();
+
+    for file in files {
+        let root = find_root(&mut parents, file.clone());
+        components.entry(root).or_default().push(file);
+    }
+
+    let mut clusters: Vec<Vec<T>> = components.into_values().collect();
+    // The sort is stable, so clusters of the same size stay in the order of their files.
+    clusters.sort_by_key(|cluster| std::cmp::Reverse(cluster.len()));
+
+    clusters
+}
+
+/// Find the root of the cluster tree that contains this file.
+/// Registers the file as its own root if it isn't known yet.
+fn find_root<T>(parents: &mut BTreeMap<T, T>, file: T) -> T
+where
+    T: Ord + Clone,
+{
+    let mut current = file;
+
+    loop {
+        let parent = parents
+            .entry(current.clone())
+            .or_insert_with(|| current.clone())
+            .clone();
+
+        if parent == current {
+            return current;
+        }
+        current = parent;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn connected_pairs() {
+        let pairs = [("a", "b"), ("c", "d"), ("b", "e"), ("e", "a")];
+
+        assert_eq!(vec![vec!["a", "b", "e"], vec!["c", "d"]], clusters(pairs));
+    }
+}
@@ -0,0 +1,406 @@
+/*
+Copyright 2022 Marek Suchánek <msuchane@redhat.com>
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Tokenize source code, so that the comparison sees the code rather than
+//! comments, formatting, and optionally the names of identifiers and literal values.
+
+use crate::cli::Language;
+
+/// The placeholder that replaces identifiers with `--code-rename-identifiers`.
+const IDENTIFIER: &str = "ID";
+/// The placeholder that replaces literals with `--code-rename-literals`.
+const LITERAL: &str = "LIT";
+
+const RUST_KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern",
+    "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub",
+    "ref", "return", "self", "Self", "static", "struct", "super", "trait", "true", "type",
+    "unsafe", "use", "where", "while",
+];
+
+const PYTHON_KEYWORDS: &[&str] = &[
+    "False", "None", "True", "and", "as", "assert", "async", "await", "break", "class", "continue",
+    "def", "del", "elif", "else", "except", "finally", "for", "from", "global", "if", "import",
+    "in", "is", "lambda", "nonlocal", "not", "or", "pass", "raise", "return", "try", "while",
+    "with", "yield",
+];
+
+/// The keywords of C and C++, which share the language.
+const C_KEYWORDS: &[&str] = &[
+    "alignas",
+    "alignof",
+    "auto",
+    "bool",
+    "break",
+    "case",
+    "catch",
+    "char",
+    "class",
+    "const",
+    "const_cast",
+    "consteval",
+    "constexpr",
+    "continue",
+    "decltype",
+    "default",
+    "delete",
+    "do",
+    "double",
+    "dynamic_cast",
+    "else",
+    "enum",
+    "explicit",
+    "export",
+    "extern",
+    "false",
+    "final",
+    "float",
+    "for",
+    "friend",
+    "goto",
+    "if",
+    "inline",
+    "int",
+    "long",
+    "mutable",
+    "namespace",
+    "new",
+    "noexcept",
+    "nullptr",
+    "operator",
+    "override",
+    "private",
+    "protected",
+    "public",
+    "register",
+    "reinterpret_cast",
+    "restrict",
+    "return",
+    "short",
+    "signed",
+    "sizeof",
+    "static",
+    "static_assert",
+    "static_cast",
+    "struct",
+    "switch",
+    "template",
+    "this",
+    "throw",
+    "true",
+    "try",
+    "typedef",
+    "typeid",
+    "typename",
+    "union",
+    "unsigned",
+    "using",
+    "virtual",
+    "void",
+    "volatile",
+    "while",
+];
+
+const JAVA_KEYWORDS: &[&str] = &[
+    "abstract",
+    "assert",
+    "boolean",
+    "break",
+    "byte",
+    "case",
+    "catch",
+    "char",
+    "class",
+    "const",
+    "continue",
+    "default",
+    "do",
+    "double",
+    "else",
+    "enum",
+    "extends",
+    "false",
+    "final",
+    "finally",
+    "float",
+    "for",
+    "if",
+    "implements",
+    "import",
+    "instanceof",
+    "int",
+    "interface",
+    "long",
+    "new",
+    "null",
+    "package",
+    "private",
+    "protected",
+    "public",
+    "return",
+    "short",
+    "static",
+    "super",
+    "switch",
+    "synchronized",
+    "this",
+    "throw",
+    "throws",
+    "true",
+    "try",
+    "void",
+    "volatile",
+    "while",
+];
+
+const SHELL_KEYWORDS: &[&str] = &[
+    "case", "do", "done", "elif", "else", "esac", "export", "fi", "for", "function", "if", "in",
+    "local", "return", "select", "then", "until", "while",
+];
+
+/// How to tokenize the code.
+#[derive(Clone, Copy)]
+pub struct Tokenizer {
+    language: Language,
+    rename_identifiers: bool,
+    rename_literals: bool,
+}
+
+impl Tokenizer {
+    pub fn new(language: Language, rename_identifiers: bool, rename_literals: bool) -> Self {
+        Self {
+            language,
+            rename_identifiers,
+            rename_literals,
+        }
+    }
+
+    /// Convert the code to a stream of tokens, without comments. The tokens on each line
+    /// are separated by a single space, and the lines without tokens are removed.
+    pub fn tokenize(self, code: &str) -> String {
+        let chars: Vec<char> = code.chars().collect();
+        let mut lines: Vec<String> = Vec::new();
+        let mut line: Vec<String> = Vec::new();
+        let mut index = 0;
+
+        while let Some(&character) = chars.get(index) {
+            let rest = &chars[index..];
+
+            if character == '\n' {
+                end_line(&mut lines, &mut line);
+                index += 1;
+            } else if character.is_whitespace() {
+                index += 1;
+            } else if self.line_comment(rest, index.checked_sub(1).map(|i| chars[i])) {
+                index += rest.iter().take_while(|&&c| c != '\n').count();
+            } else if let Some(length) = self.block_comment(rest) {
+                index += length;
+            } else if let Some(length) = self.string(rest) {
+                self.push_literal(&mut line, &rest[..length]);
+                index += length;
+            } else if character.is_ascii_digit() {
+                let length = rest
+                    .iter()
+                    .take_while(|&&c| c.is_alphanumeric() || c == '_' || c == '.')
+                    .count();
+                self.push_literal(&mut line, &rest[..length]);
+                index += length;
+            } else if character.is_alphabetic() || character == '_' {
+                let length = rest
+                    .iter()
+                    .take_while(|&&c| c.is_alphanumeric() || c == '_')
+                    .count();
+                let word: String = rest[..length].iter().collect();
+                if self.rename_identifiers && !self.keywords().contains(&word.as_str()) {
+                    line.push(IDENTIFIER.to_string());
+                } else {
+                    line.push(word);
+                }
+                index += length;
+            } else {
+                line.push(character.to_string());
+                index += 1;
+            }
+        }
+        end_line(&mut lines, &mut line);
+
+        lines.join("\n")
+    }
+
+    fn keywords(self) -> &'static [&'static str] {
+        match self.language {
+            Language::Rust => RUST_KEYWORDS,
+            Language::Python => PYTHON_KEYWORDS,
+            Language::C => C_KEYWORDS,
+            Language::Java => JAVA_KEYWORDS,
+            Language::Shell => SHELL_KEYWORDS,
+        }
+    }
+
+    /// Check whether a line comment starts here, after the previous character.
+    /// In shell, a comment only starts a word, so `$#` or `a#b` aren't comments.
+    fn line_comment(self, rest: &[char], previous: Option<char>) -> bool {
+        match self.language {
+            Language::Rust | Language::C | Language::Java => rest.starts_with(&['/', '/']),
+            Language::Python => rest[0] == '#',
+            Language::Shell => {
+                rest[0] == '#' && previous.map_or(true, |c| c.is_whitespace() || ";|&(".contains(c))
+            }
+        }
+    }
+
+    /// The length of the block comment that starts here, if any.
+    /// Block comments nest in Rust, but not in C and Java.
+    fn block_comment(self, rest: &[char]) -> Option<usize> {
+        if !matches!(self.language, Language::Rust | Language::C | Language::Java)
+            || !rest.starts_with(&['/', '*'])
+        {
+            return None;
+        }
+
+        let nested = self.language == Language::Rust;
+        let mut depth = 0_usize;
+        let mut index = 0;
+
+        while index < rest.len() {
+            if rest[index..].starts_with(&['/', '*']) && (nested || depth == 0) {
+                depth += 1;
+                index += 2;
+            } else if rest[index..].starts_with(&['*', '/']) {
+                depth -= 1;
+                index += 2;
+                if depth == 0 {
+                    return Some(index);
+                }
+            } else {
+                index += 1;
+            }
+        }
+
+        // An unterminated comment lasts until the end of the file.
+        Some(rest.len())
+    }
+
+    /// The length of the string or character literal that starts here, if any.
+    fn string(self, rest: &[char]) -> Option<usize> {
+        let quote = rest[0];
+        if quote != '"' && quote != '\'' {
+            return None;
+        }
+
+        // Python strings can be triple-quoted, such as docstrings.
+        if self.language == Language::Python && rest.starts_with(&[quote, quote, quote]) {
+            let delimiter = [quote, quote, quote];
+            let end = (3..rest.len())
+                .find(|&index| rest[index..].starts_with(&delimiter) && rest[index - 1] != '\\')
+                .map_or(rest.len(), |index| index + 3);
+            return Some(end);
+        }
+
+        // In Rust, a quote also starts a lifetime, such as `'a`, which isn't a literal.
+        if self.language == Language::Rust && quote == '\'' {
+            let is_char = rest.get(1) == Some(&'\\') || rest.get(2) == Some(&'\'');
+            if !is_char {
+                return None;
+            }
+        }
+
+        // In shell, single-quoted strings have no escapes.
+        let escapes = !(self.language == Language::Shell && quote == '\'');
+        let mut index = 1;
+
+        while index < rest.len() {
+            match rest[index] {
+                '\\' if escapes => index += 2,
+                character if character == quote => return Some(index + 1),
+                _ => index += 1,
+            }
+        }
+
+        Some(rest.len())
+    }
+
+    fn push_literal(self, line: &mut Vec<String>, literal: &[char]) {
+        if self.rename_literals {
+            line.push(LITERAL.to_string());
+        } else {
+            line.push(literal.iter().collect());
+        }
+    }
+}
+
+/// Finish the line of tokens, unless it's empty.
+fn end_line(lines: &mut Vec<String>, line: &mut Vec<String>) {
+    if !line.is_empty() {
+        lines.push(line.join(" "));
+        line.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokenized_code() {
+        let original = "// Add the numbers.\n\
+            fn add(a: i32, b: i32) -> i32 {\n\
+            \x20   /* A /* nested */ comment. */\n\
+            \x20   a + b + 1 // Off by one.\n\
+            }";
+        let renamed = "fn sum(x: i32,\n\
+            \x20      y: i32) -> i32 { x + y + 2 }";
+
+        let tokenizer = Tokenizer::new(Language::Rust, false, false);
+        assert_eq!(
+            "fn add ( a : i32 , b : i32 ) - > i32 {\na + b + 1\n}",
+            tokenizer.tokenize(original)
+        );
+
+        let anonymous = Tokenizer::new(Language::Rust, true, true);
+        assert_eq!(
+            "fn ID ( ID : ID , ID : ID ) - > ID {\nID + ID + LIT\n}",
+            anonymous.tokenize(original)
+        );
+        assert_eq!(
+            "fn ID ( ID : ID ,\nID : ID ) - > ID { ID + ID + LIT }",
+            anonymous.tokenize(renamed)
+        );
+
+        let python = Tokenizer::new(Language::Python, false, true);
+        assert_eq!(
+            "def f ( ) :\nLIT\nreturn LIT",
+            python.tokenize("def f():\n    \"\"\"A # docstring.\"\"\"\n    return 'x'  # Comment.")
+        );
+
+        let shell = Tokenizer::new(Language::Shell, false, false);
+        assert_eq!(
+            "echo $ # 'a # b'",
+            shell.tokenize("echo $# 'a # b' # Comment.")
+        );
+        assert_eq!(
+            "echo a # b $ { # PATH } ;\ntrue |",
+            shell.tokenize("echo a#b ${#PATH};# Comment.\ntrue |# Comment.\n# Comment.")
+        );
+
+        // The C++ keywords stay with the renamed identifiers.
+        let cpp = Tokenizer::new(Language::C, true, false);
+        assert_eq!(
+            "template < typename ID > class ID : public ID { } ;",
+            cpp.tokenize("template <typename T> class Box : public Base {};")
+        );
+    }
+}
@@ -14,20 +14,84 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
-use std::path::{Path, PathBuf};
-
 use indicatif::{ParallelProgressIterator, ProgressBar, ProgressStyle};
-use owo_colors::{OwoColorize, Stream};
 use rayon::prelude::*;
+use similar::{ChangeTag, TextDiff};
 
+use crate::cli::{Metric, Tokens};
 use crate::percentage::Percentage;
-use crate::{Cli, File};
+use crate::tfidf;
+use crate::tokens;
+use crate::{File, Options};
 
 #[derive(Debug)]
 pub struct Comparison<'a> {
-    pub path1: &'a Path,
-    pub path2: &'a PathBuf,
+    pub file1: &'a File,
+    pub file2: &'a File,
     pub similarity_pct: Percentage,
+    /// The metric that produced the similarity.
+    pub metric: Metric,
+    /// The raw similarity score between 0.0 and 1.0, as calculated by the metric.
+    pub score: f64,
+    /// The trigram similarity used to pre-select the pair.
+    pub trigram: f64,
+    /// Additional, more expensive statistics, if the user requested them.
+    pub details: Option<Details>,
+}
+
+/// Statistics about the differences between two similar files.
+/// Calculating them takes extra time, so they're only available on request.
+#[derive(Debug)]
+pub struct Details {
+    /// The Levenshtein distance in the compared units, either characters or tokens.
+    /// Only available with the Levenshtein metric, which already calculates it,
+    /// or in the character mode.
+    pub edit_distance: Option<usize>,
+    pub lines_added: usize,
+    pub lines_removed: usize,
+    pub identical: bool,
+}
+
+impl Details {
+    fn new(pair: &ComparedPair, score: f64, options: &Options) -> Self {
+        let (file1, file2) = (pair.file1, pair.file2);
+        let characters = options.tokens == Tokens::Characters;
+        let diff = TextDiff::from_lines(&file1.content, &file2.content);
+
+        let mut lines_added = 0;
+        let mut lines_removed = 0;
+
+        for change in diff.iter_all_changes() {
+            match change.tag() {
+                ChangeTag::Insert => lines_added += 1,
+                ChangeTag::Delete => lines_removed += 1,
+                ChangeTag::Equal => {}
+            }
+        }
+
+        Self {
+            edit_distance: match pair.metric {
+                Metric::Levenshtein if characters => Some(levenshtein_distance(
+                    score,
+                    file1.content.chars().count(),
+                    file2.content.chars().count(),
+                )),
+                Metric::Levenshtein => Some(levenshtein_distance(
+                    score,
+                    file1.tokens.len(),
+                    file2.tokens.len(),
+                )),
+                Metric::Jaro | Metric::Trigram if characters => {
+                    Some(strsim::levenshtein(&file1.content, &file2.content))
+                }
+                // The character distance would contradict the metric.
+                Metric::Jaro | Metric::Trigram | Metric::Tfidf => None,
+            },
+            lines_added,
+            lines_removed,
+            identical: file1.content == file2.content,
+        }
+    }
 }
 
 /// Groups together the various data and options used in an iteration
@@ -36,16 +100,48 @@ struct ComparedPair<'a> {
     file1: &'a File,
     file2: &'a File,
     trigram: f64,
+    threshold: f64,
+    metric: Metric,
+    tokens: Tokens,
 }
 
-impl ComparedPair<'_> {
+impl<'a> ComparedPair<'a> {
+    fn new(file1: &'a File, file2: &'a File, options: &Options) -> Self {
+        // If the files have different settings from rules, the lower threshold applies,
+        // together with the metric of the same rule.
+        let stricter = if file1.threshold <= file2.threshold {
+            file1
+        } else {
+            file2
+        };
+
+        Self {
+            file1,
+            file2,
+            trigram: trigram_f64(file1, file2, options),
+            threshold: stricter.threshold,
+            metric: stricter.metric,
+            tokens: options.tokens,
+        }
+    }
+
     /// Calculating the trigram only takes
     /// about 10% of the time needed for Jaro, or about 5% of Levenshtein.
     /// Use the value to pre-select files for comparison.
-    fn trigram_preselect(&self, options: &Cli) -> bool {
+    fn trigram_preselect(&self) -> bool {
+        // The TF-IDF metric weighs words differently than the trigrams do.
+        if self.metric == Metric::Tfidf {
+            return true;
+        }
+        // The trigrams of tokens are much rarer to share than the trigrams of characters,
+        // so their similarity is no bound for the other metrics.
+        if self.tokens != Tokens::Characters && self.metric != Metric::Trigram {
+            return true;
+        }
+
         // Require that the trigram similarity is at least half of the set similarity threshold.
         // If it's lower than half of the threshold, skip the actual, expensive comparison.
-        if self.trigram < options.threshold / 2.0 {
+        if self.trigram < self.threshold / 2.0 {
             log::debug!(
                 "Trigram similarity below the threshold: {:.3}\n\t→{}\n\t→{}",
                 self.trigram,
@@ -59,14 +155,14 @@ impl ComparedPair<'_> {
     }
 }
 
-pub fn comparisons<'a, T>(combinations: T, options: &Cli) -> Vec<Comparison<'a>>
+pub fn comparisons<'a, T>(combinations: T, options: &Options, progress: bool) -> Vec<Comparison<'a>>
 where
     T: Iterator<Item = (&'a File, &'a File)> + Send + ExactSizeIterator,
 {
     log::debug!("Comparing files…");
 
     // If the `progress` command-line option isn't active, hide the progress bar.
-    let progress_bar = if options.progress {
+    let progress_bar = if progress {
         // The total number of combinations, and also of needed comparisons.
         let total = combinations.len();
         progress_bar(total)
@@ -75,79 +171,102 @@ where
         ProgressBar::hidden()
     };
 
-    combinations
+    let mut comparisons: Vec<Comparison> = combinations
         // Convert the current sequential iterator to a parallel one.
         .par_bridge()
         .progress_with(progress_bar)
-        .map(|(file1, file2)| ComparedPair {
-            file1,
-            file2,
-            trigram: trigram_f64(&file1.content, &file2.content),
-        })
-        .filter(|pair| pair.trigram_preselect(options))
+        // Prose is only compared with prose, and code blocks with code blocks.
+        .filter(|(file1, file2)| file1.part == file2.part)
+        .map(|(file1, file2)| ComparedPair::new(file1, file2, options))
+        .filter(ComparedPair::trigram_preselect)
         .filter_map(|pair| compare_files(&pair, options))
-        .collect()
+        .collect();
+
+    // Sort from highest to lowest. The parallel comparison finishes in a random order,
+    // so sort pairs with the same similarity by their paths to keep the results stable.
+    comparisons.sort_unstable_by(|a, b| {
+        b.similarity_pct
+            .0
+            .total_cmp(&a.similarity_pct.0)
+            .then_with(|| a.file1.path.cmp(&b.file1.path))
+            .then_with(|| a.file2.path.cmp(&b.file2.path))
+    });
+
+    comparisons
 }
 
-/// Compare the two files. Print out the report and return a struct with the information.
+/// Compare the two files and return a struct with the information.
 /// Returns None if the files were skipped or if they are more different than the threshold.
-fn compare_files<'a>(pair: &ComparedPair<'a>, options: &Cli) -> Option<Comparison<'a>> {
+fn compare_files<'a>(pair: &ComparedPair<'a>, options: &Options) -> Option<Comparison<'a>> {
     // The user can pick the accuracy and speed of the comparison.
-    let similarity = match options.fast {
+    let metric = pair.metric;
+    let (file1, file2) = (pair.file1, pair.file2);
+    let similarity = match metric {
+        // The token mode compares the same metrics on the sequences of tokens.
+        Metric::Levenshtein if options.tokens != Tokens::Characters => {
+            tokens::levenshtein(&file1.tokens, &file2.tokens)
+        }
+        Metric::Jaro if options.tokens != Tokens::Characters => {
+            tokens::jaro(&file1.tokens, &file2.tokens)
+        }
         // Levenshtein is slow and accurate. Default.
-        0 => strsim::normalized_levenshtein(&pair.file1.content, &pair.file2.content),
+        Metric::Levenshtein => {
+            strsim::normalized_levenshtein(&pair.file1.content, &pair.file2.content)
+        }
         // Jaro is about 200% the speed of Levenshtein.
-        1 => strsim::jaro(&pair.file1.content, &pair.file2.content),
+        Metric::Jaro => strsim::jaro(&pair.file1.content, &pair.file2.content),
+        Metric::Tfidf => tfidf::cosine(&file1.weights, &file2.weights),
         // Trigram si rudimentary, but very fast.
         // Reuse the value calculated in the iterator pipeline earlier.
-        _ => pair.trigram,
+        Metric::Trigram => pair.trigram,
     };
 
-    if similarity > options.threshold {
+    if similarity > pair.threshold {
         let percent = Percentage::from(similarity);
-        // Prepare the listing of the file pair before printing.
-        let file_display = format!(
-            "  ‣ {}\n  ‣ {}",
-            pair.file1.path.display(),
-            pair.file2.path.display(),
-        );
-
-        if similarity >= 1.0 {
-            let message = format!("These two files are identical ({:.1}%):", percent.rounded());
-            log::info!(
-                "{}\n{}",
-                message.if_supports_color(Stream::Stdout, OwoColorize::red),
-                file_display,
-            );
-        } else {
-            let message = format!("These two files are similar ({:.1}%):", percent.rounded());
-            log::info!(
-                "{}\n{}",
-                message.if_supports_color(Stream::Stdout, OwoColorize::yellow),
-                file_display
-            );
-        };
-        log::debug!(
-            "Similarity above the threshold:\n\tDistance: {:.3}",
-            similarity,
-        );
+        log::debug!("Similarity above the threshold:\n\tDistance: {similarity:.3}");
 
         Some(Comparison {
-            path1: &pair.file1.path,
-            path2: &pair.file2.path,
+            file1: pair.file1,
+            file2: pair.file2,
             similarity_pct: percent,
+            metric,
+            score: similarity,
+            trigram: pair.trigram,
+            details: options
+                .details
+                .then(|| Details::new(pair, similarity, options)),
         })
     } else {
         // The files are too different.
-        log::debug!("Similarity below the threshold:{:.3}", similarity,);
+        log::debug!("Similarity below the threshold: {similarity:.3}");
         None
     }
 }
 
+/// Recover the Levenshtein distance from the normalized similarity, which divides
+/// the distance by the length of the longer sequence.
+// The lengths are far below the precision limit of `f64`, and the rounded distance
+// is a small, positive number.
+#[allow(
+    clippy::cast_precision_loss,
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss
+)]
+fn levenshtein_distance(similarity: f64, length1: usize, length2: usize) -> usize {
+    let longest = length1.max(length2) as f64;
+
+    ((1.0 - similarity) * longest).round() as usize
+}
+
 /// Calculate the trigram metric and convert to f64,
 /// so that we can easily compare it with the other metrics.
-fn trigram_f64(content1: &str, content2: &str) -> f64 {
-    f64::from(trigram::similarity(content1, content2))
+/// In the token mode, the trigrams consist of tokens rather than characters.
+fn trigram_f64(file1: &File, file2: &File, options: &Options) -> f64 {
+    if options.tokens == Tokens::Characters {
+        f64::from(trigram::similarity(&file1.content, &file2.content))
+    } else {
+        tokens::trigram(&file1.tokens, &file2.tokens)
+    }
 }
 
 /// Build a progress bar to report the progress of comaprisons.
@@ -160,3 +279,118 @@ fn progress_bar(total_length: usize) -> ProgressBar {
 
     ProgressBar::new(total_length as u64).with_style(progress_style)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    fn details(metric: Metric) -> Option<usize> {
+        let file = |path, content| File {
+            threshold: 0.5,
+            metric,
+            weights: vec![(0, 1.0)],
+            ..File::with_content(path, content)
+        };
+        let file1 = file("a", "The quick brown fox jumps.");
+        let file2 = file("b", "The quick brown fox jumped.");
+        let options = Options {
+            details: true,
+            ..Options::default()
+        };
+
+        let pair = ComparedPair::new(&file1, &file2, &options);
+        let comparison = compare_files(&pair, &options).unwrap();
+
+        comparison.details.unwrap().edit_distance
+    }
+
+    #[test]
+    fn edit_distance_follows_the_metric() {
+        assert_eq!(Some(2), details(Metric::Levenshtein));
+        assert_eq!(Some(2), details(Metric::Jaro));
+        assert_eq!(None, details(Metric::Tfidf));
+    }
+
+    #[test]
+    fn lower_threshold_applies_with_its_metric() {
+        let file1 = File {
+            threshold: 0.9,
+            metric: Metric::Jaro,
+            ..File::with_content("a", "")
+        };
+        let file2 = File {
+            threshold: 0.7,
+            metric: Metric::Trigram,
+            ..File::with_content("b", "")
+        };
+        let options = Options::default();
+
+        for (first, second) in [(&file1, &file2), (&file2, &file1)] {
+            let pair = ComparedPair::new(first, second, &options);
+            assert!((pair.threshold - 0.7).abs() < f64::EPSILON);
+            assert_eq!(Metric::Trigram, pair.metric);
+        }
+    }
+
+    #[test]
+    fn code_blocks_only_match_code_blocks() {
+        let prose = File::with_content("a.md", "make install");
+        let code = |path| File {
+            part: crate::Part::CodeBlocks,
+            ..File::with_content(path, "make install")
+        };
+        let (code1, code2) = (code("a.md#code"), code("b.md#code"));
+        let options = Options::default();
+
+        let pairs = [(&prose, &code1), (&prose, &code2), (&code1, &code2)];
+        let found: Vec<(&Path, &Path)> = comparisons(pairs.into_iter(), &options, false)
+            .iter()
+            .map(|comparison| {
+                (
+                    comparison.file1.path.as_path(),
+                    comparison.file2.path.as_path(),
+                )
+            })
+            .collect();
+
+        assert_eq!(
+            vec![(Path::new("a.md#code"), Path::new("b.md#code"))],
+            found
+        );
+    }
+
+    #[test]
+    fn token_trigrams_preselect_nothing() {
+        // Every 7th word differs, which breaks many more token trigrams than it changes tokens.
+        let text = |changed: &str| {
+            (0..700)
+                .map(|index| {
+                    if index % 7 == 0 {
+                        format!("{changed}{index}")
+                    } else {
+                        format!("word{index}")
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        let options = Options {
+            tokens: Tokens::Whitespace,
+            ..Options::default()
+        };
+        let mut files = [
+            File::with_content("a", &text("old")),
+            File::with_content("b", &text("new")),
+        ];
+        tokens::tokenize_files(&mut files, &options).unwrap();
+
+        let pair = ComparedPair::new(&files[0], &files[1], &options);
+        assert!(pair.trigram < options.threshold / 2.0);
+
+        let found = comparisons([(&files[0], &files[1])].into_iter(), &options, false);
+        assert_eq!(1, found.len());
+        assert!(found[0].score > options.threshold);
+    }
+}
@@ -0,0 +1,554 @@
+/*
+Copyright 2022 Marek Suchánek <msuchane@redhat.com>
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use color_eyre::eyre::{bail, eyre, WrapErr};
+use color_eyre::Result;
+use regex::Regex;
+use serde::{Deserialize, Deserializer};
+
+use crate::cli::{
+    parse_size, Block, CodeBlocks, Format, Metric, Normalization, Replace, Rule, Tokens,
+};
+use crate::Cli;
+
+/// The name of the configuration file that the program looks for
+/// in the compared directory and its parents.
+const CONFIG_FILE_NAME: &str = "near-facsimile.toml";
+
+/// The options that the configuration file can set. Each of them corresponds
+/// to a command-line option of the same name.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+struct Settings {
+    threshold: Option<f64>,
+    fast: Option<u8>,
+    metric: Option<ConfigMetric>,
+    csv: Option<PathBuf>,
+    json: Option<PathBuf>,
+    markdown: Option<PathBuf>,
+    junit: Option<PathBuf>,
+    diff_dir: Option<PathBuf>,
+    details: Option<bool>,
+    ignore_file: Option<Vec<String>>,
+    ignore_ext: Option<Vec<String>>,
+    require_file: Option<Vec<String>>,
+    require_ext: Option<Vec<String>>,
+    files_from: Option<PathBuf>,
+    hidden: Option<bool>,
+    no_ignore: Option<bool>,
+    follow_symlinks: Option<bool>,
+    max_depth: Option<usize>,
+    archives: Option<bool>,
+    encoding: Option<String>,
+    max_file_size: Option<ConfigSize>,
+    min_file_size: Option<ConfigSize>,
+    min_lines: Option<usize>,
+    min_words: Option<usize>,
+    list_skipped: Option<bool>,
+    include: Option<Vec<String>>,
+    exclude: Option<Vec<String>>,
+    skip_lines: Option<Vec<ConfigRegex>>,
+    format: Option<ConfigFormat>,
+    asciidoc_includes: Option<bool>,
+    asciidoc_attributes: Option<PathBuf>,
+    asciidoc_ignore_anchors: Option<bool>,
+    asciidoc_ignore_conditionals: Option<bool>,
+    markdown_code: Option<ConfigCodeBlocks>,
+    markup_structure: Option<bool>,
+    code_rename_identifiers: Option<bool>,
+    code_rename_literals: Option<bool>,
+    tokens: Option<ConfigTokens>,
+    stopwords: Option<PathBuf>,
+    boilerplate: Option<f64>,
+    strip_boilerplate: Option<bool>,
+    skip_block: Option<Vec<ConfigBlock>>,
+    replace: Option<Vec<ConfigReplace>>,
+    normalize: Option<Vec<ConfigNormalization>>,
+    progress: Option<bool>,
+    rule: Option<Vec<ConfigRule>>,
+    /// Named sets of options that override the rest of the file when selected.
+    #[serde(default)]
+    profile: BTreeMap<String, Settings>,
+}
+
+/// A rule for the files that match a glob pattern, as written in the `[[rule]]` tables.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+struct ConfigRule {
+    pattern: String,
+    threshold: Option<ConfigThreshold>,
+    metric: Option<ConfigMetric>,
+    #[serde(default)]
+    skip_lines: Vec<ConfigRegex>,
+    #[serde(default)]
+    ignore: bool,
+}
+
+impl From<ConfigRule> for Rule {
+    fn from(rule: ConfigRule) -> Self {
+        Self {
+            pattern: rule.pattern,
+            threshold: rule.threshold.map(|threshold| threshold.0),
+            metric: rule.metric.map(|metric| metric.0),
+            skip_lines: rule.skip_lines.into_iter().map(|regex| regex.0).collect(),
+            ignore: rule.ignore,
+        }
+    }
+}
+
+/// A threshold percentage, validated and converted to a decimal value while reading
+/// the configuration file.
+#[derive(Debug)]
+struct ConfigThreshold(f64);
+
+impl<'de> Deserialize<'de> for ConfigThreshold {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let percentage = f64::deserialize(deserializer)?;
+        Rule::threshold_from_pct(percentage)
+            .map(Self)
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// A metric name, validated while reading the configuration file.
+#[derive(Debug)]
+struct ConfigMetric(Metric);
+
+impl<'de> Deserialize<'de> for ConfigMetric {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let name = String::deserialize(deserializer)?;
+        name.parse().map(Self).map_err(serde::de::Error::custom)
+    }
+}
+
+/// A format name, validated while reading the configuration file.
+#[derive(Debug)]
+struct ConfigFormat(Format);
+
+impl<'de> Deserialize<'de> for ConfigFormat {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let name = String::deserialize(deserializer)?;
+        name.parse().map(Self).map_err(serde::de::Error::custom)
+    }
+}
+
+/// A code block mode, validated while reading the configuration file.
+#[derive(Debug)]
+struct ConfigCodeBlocks(CodeBlocks);
+
+impl<'de> Deserialize<'de> for ConfigCodeBlocks {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let name = String::deserialize(deserializer)?;
+        name.parse().map(Self).map_err(serde::de::Error::custom)
+    }
+}
+
+/// A token mode, validated while reading the configuration file.
+#[derive(Debug)]
+struct ConfigTokens(Tokens);
+
+impl<'de> Deserialize<'de> for ConfigTokens {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let name = String::deserialize(deserializer)?;
+        name.parse().map(Self).map_err(serde::de::Error::custom)
+    }
+}
+
+/// A block preset or a block in the `<START>..<END>` format, validated while reading
+/// the configuration file.
+#[derive(Debug)]
+struct ConfigBlock(Block);
+
+impl<'de> Deserialize<'de> for ConfigBlock {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let text = String::deserialize(deserializer)?;
+        text.parse().map(Self).map_err(serde::de::Error::custom)
+    }
+}
+
+/// A substitution in the `<REGEX>=<REPLACEMENT>` format, validated while reading
+/// the configuration file.
+#[derive(Debug)]
+struct ConfigReplace(Replace);
+
+impl<'de> Deserialize<'de> for ConfigReplace {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let text = String::deserialize(deserializer)?;
+        text.parse().map(Self).map_err(serde::de::Error::custom)
+    }
+}
+
+/// A normalization name, validated while reading the configuration file.
+#[derive(Debug)]
+struct ConfigNormalization(Normalization);
+
+impl<'de> Deserialize<'de> for ConfigNormalization {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let name = String::deserialize(deserializer)?;
+        name.parse().map(Self).map_err(serde::de::Error::custom)
+    }
+}
+
+/// A file size, either as a number of bytes or as a string with a suffix, such as `"1M"`.
+#[derive(Debug)]
+struct ConfigSize(u64);
+
+impl<'de> Deserialize<'de> for ConfigSize {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Size {
+            Bytes(u64),
+            Text(String),
+        }
+
+        match Size::deserialize(deserializer)? {
+            Size::Bytes(bytes) => Ok(Self(bytes)),
+            Size::Text(text) => parse_size(&text)
+                .map(Self)
+                .map_err(serde::de::Error::custom),
+        }
+    }
+}
+
+/// A regular expression that's validated while reading the configuration file,
+/// so that the error points to its line.
+#[derive(Debug)]
+struct ConfigRegex(Regex);
+
+impl<'de> Deserialize<'de> for ConfigRegex {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let text = String::deserialize(deserializer)?;
+        Regex::new(&text)
+            .map(Self)
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// Load the configuration file, if any, and apply its options to those options
+/// that the user didn't set on the command line.
+///
+/// # Errors
+///
+/// Fails if the configuration file can't be read, if it's invalid,
+/// or if the selected profile doesn't exist.
+pub fn load_config(options: &mut Cli) -> Result<()> {
+    let Some(file) = config_file(options)? else {
+        if let Some(profile) = &options.profile {
+            bail!("The `{profile}` profile requires a configuration file, but none was found.");
+        }
+        return Ok(());
+    };
+
+    log::debug!("Loading the configuration file: {}", file.display());
+
+    let mut settings = read_settings(&file)?;
+    // Relative paths in the file refer to its directory, wherever the program runs.
+    let dir = file.parent().unwrap_or_else(|| Path::new(""));
+    settings.resolve_paths(dir);
+
+    // Profiles can't define further profiles.
+    if let Some(name) = settings
+        .profile
+        .iter()
+        .find_map(|(name, profile)| (!profile.profile.is_empty()).then_some(name))
+    {
+        bail!(
+            "Invalid configuration file: {}\nThe `{name}` profile can't contain profiles.",
+            file.display()
+        );
+    }
+
+    let profile = match &options.profile {
+        Some(name) => Some(settings.profile.remove(name).ok_or_else(|| {
+            eyre!(
+                "The `{name}` profile doesn't exist in the configuration file: {}",
+                file.display()
+            )
+        })?),
+        None => None,
+    };
+
+    // The profile overrides the rest of the file, and the command line overrides both.
+    settings.apply(options);
+    if let Some(mut profile) = profile {
+        profile.resolve_paths(dir);
+        profile.apply(options);
+    }
+
+    check_requirements(options)
+        .wrap_err_with(|| format!("Invalid configuration file: {}", file.display()))
+}
+
+/// Check the dependencies between the options, which clap only checks
+/// on the command line, after merging the configuration file.
+fn check_requirements(options: &Cli) -> Result<()> {
+    if options.strip_boilerplate && options.boilerplate.is_none() {
+        bail!("The `strip-boilerplate` option requires the `boilerplate` option.");
+    }
+
+    Ok(())
+}
+
+/// Find the configuration file: either the one that the user specified,
+/// or the nearest one in the first compared directory or its parents.
+fn config_file(options: &Cli) -> Result<Option<PathBuf>> {
+    if let Some(file) = &options.config {
+        return Ok(Some(file.clone()));
+    }
+
+    // The `path` option always has at least its default value.
+    let first = &options.path[0];
+    let dir = first
+        .canonicalize()
+        .wrap_err_with(|| format!("Failed to access the directory: {}", first.display()))?;
+
+    Ok(dir
+        .ancestors()
+        .map(|ancestor| ancestor.join(CONFIG_FILE_NAME))
+        .find(|candidate| candidate.is_file()))
+}
+
+/// Read and validate the configuration file.
+fn read_settings(file: &Path) -> Result<Settings> {
+    let text = fs::read_to_string(file)
+        .wrap_err_with(|| format!("Failed to read the configuration file: {}", file.display()))?;
+
+    // The TOML errors include the line and column of the problem.
+    toml::from_str(&text)
+        .wrap_err_with(|| format!("Invalid configuration file: {}", file.display()))
+}
+
+impl Settings {
+    /// Resolve the relative paths against the directory of the configuration file.
+    /// The `-` path of the `files-from` option stays the standard input.
+    fn resolve_paths(&mut self, dir: &Path) {
+        for path in [
+            &mut self.csv,
+            &mut self.json,
+            &mut self.markdown,
+            &mut self.junit,
+            &mut self.diff_dir,
+            &mut self.files_from,
+            &mut self.asciidoc_attributes,
+            &mut self.stopwords,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            if path.is_relative() && path != Path::new("-") {
+                *path = dir.join(&*path);
+            }
+        }
+    }
+
+    /// Set the options that the file specifies, unless the user set them on the command line.
+    fn apply(self, options: &mut Cli) {
+        // Set the option of the same name, if the file specifies it.
+        // The clap ID of each option is the name of its field.
+        macro_rules! apply {
+            ($field:ident) => {
+                apply!($field, self.$field)
+            };
+            ($field:ident, $value:expr) => {
+                if let Some(value) = $value {
+                    if !options.is_explicit(stringify!($field)) {
+                        options.$field = value;
+                    }
+                }
+            };
+        }
+
+        let os_strings = |strings: Option<Vec<String>>| -> Option<Vec<OsString>> {
+            strings.map(|strings| strings.into_iter().map(OsString::from).collect())
+        };
+
+        // The file specifies the threshold as a percentage, same as the command line.
+        apply!(threshold, self.threshold.map(|threshold| threshold / 100.0));
+        // Both `--fast` and `--metric` select the metric, so either one on the command line
+        // overrides both of them in the file.
+        if !options.is_explicit("fast") && !options.is_explicit("metric") {
+            apply!(fast);
+            apply!(metric, self.metric.map(|metric| Some(metric.0)));
+        }
+        apply!(csv, self.csv.map(Some));
+        apply!(json, self.json.map(Some));
+        apply!(markdown, self.markdown.map(Some));
+        apply!(junit, self.junit.map(Some));
+        apply!(diff_dir, self.diff_dir.map(Some));
+        apply!(details);
+        apply!(ignore_file, os_strings(self.ignore_file));
+        apply!(ignore_ext, os_strings(self.ignore_ext));
+        apply!(require_file, os_strings(self.require_file));
+        apply!(require_ext, os_strings(self.require_ext));
+        apply!(files_from, self.files_from.map(Some));
+        apply!(hidden);
+        apply!(no_ignore);
+        apply!(follow_symlinks);
+        apply!(max_depth, self.max_depth.map(Some));
+        apply!(archives);
+        apply!(encoding);
+        apply!(max_file_size, self.max_file_size.map(|size| Some(size.0)));
+        apply!(min_file_size, self.min_file_size.map(|size| Some(size.0)));
+        apply!(min_lines, self.min_lines.map(Some));
+        apply!(min_words, self.min_words.map(Some));
+        apply!(list_skipped);
+        apply!(include);
+        apply!(exclude);
+        apply!(
+            skip_lines,
+            self.skip_lines
+                .map(|regexes| regexes.into_iter().map(|regex| regex.0).collect())
+        );
+        apply!(format, self.format.map(|format| format.0));
+        apply!(asciidoc_includes);
+        apply!(asciidoc_attributes, self.asciidoc_attributes.map(Some));
+        apply!(asciidoc_ignore_anchors);
+        apply!(asciidoc_ignore_conditionals);
+        apply!(markdown_code, self.markdown_code.map(|mode| mode.0));
+        apply!(markup_structure);
+        apply!(code_rename_identifiers);
+        apply!(code_rename_literals);
+        apply!(tokens, self.tokens.map(|mode| mode.0));
+        apply!(stopwords, self.stopwords.map(Some));
+        apply!(boilerplate, self.boilerplate.map(Some));
+        apply!(strip_boilerplate);
+        apply!(
+            skip_block,
+            self.skip_block
+                .map(|blocks| blocks.into_iter().map(|block| block.0).collect())
+        );
+        apply!(
+            replace,
+            self.replace
+                .map(|replacements| replacements.into_iter().map(|replace| replace.0).collect())
+        );
+        apply!(
+            normalize,
+            self.normalize
+                .map(|kinds| kinds.into_iter().map(|kind| kind.0).collect())
+        );
+        apply!(progress);
+        apply!(
+            rule,
+            self.rule
+                .map(|rules| rules.into_iter().map(Rule::from).collect())
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+
+    #[test]
+    fn errors_point_to_lines() {
+        let unknown = toml::from_str::<Settings>("threshold = 90.0\nthreshhold = 80.0\n");
+        assert!(unknown.unwrap_err().to_string().contains("line 2"));
+
+        let regex = toml::from_str::<Settings>("[profile.ci]\nskip-lines = [\"^//\", \"(\"]\n");
+        assert!(regex.unwrap_err().to_string().contains("line 2"));
+    }
+
+    #[test]
+    fn paths_relative_to_the_file() {
+        let mut settings = toml::from_str::<Settings>(
+            "json = \"out/results.json\"\nfiles-from = \"-\"\nstopwords = \"/etc/stopwords\"\n",
+        )
+        .unwrap();
+        settings.resolve_paths(Path::new("docs"));
+
+        assert_eq!(Some(PathBuf::from("docs/out/results.json")), settings.json);
+        assert_eq!(Some(PathBuf::from("-")), settings.files_from);
+        assert_eq!(Some(PathBuf::from("/etc/stopwords")), settings.stopwords);
+    }
+
+    #[test]
+    fn requirements_after_merging() {
+        let mut options = Cli::parse_from(["near-facsimile"]);
+        toml::from_str::<Settings>("strip-boilerplate = true\n")
+            .unwrap()
+            .apply(&mut options);
+        assert!(check_requirements(&options).is_err());
+
+        toml::from_str::<Settings>("boilerplate = 50.0\n")
+            .unwrap()
+            .apply(&mut options);
+        assert!(check_requirements(&options).is_ok());
+    }
+
+    #[test]
+    fn command_line_metric_wins() {
+        let file = "metric = \"levenshtein\"\nfast = 1\n";
+
+        let mut options = Cli::parse_from(["near-facsimile", "-ff"]);
+        options.explicit = vec!["fast".to_string()];
+        toml::from_str::<Settings>(file)
+            .unwrap()
+            .apply(&mut options);
+        assert_eq!(Metric::Trigram, options.metric());
+
+        let mut options = Cli::parse_from(["near-facsimile", "--metric", "jaro"]);
+        options.explicit = vec!["metric".to_string()];
+        toml::from_str::<Settings>(file)
+            .unwrap()
+            .apply(&mut options);
+        assert_eq!((Metric::Jaro, 0), (options.metric(), options.fast));
+
+        let mut options = Cli::parse_from(["near-facsimile"]);
+        toml::from_str::<Settings>(file)
+            .unwrap()
+            .apply(&mut options);
+        assert_eq!(Metric::Levenshtein, options.metric());
+    }
+}
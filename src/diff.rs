@@ -0,0 +1,308 @@
+/*
+Copyright 2022 Marek Suchánek <msuchane@redhat.com>
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+use color_eyre::Result;
+use similar::{capture_diff_slices, Algorithm, DiffTag};
+
+use crate::serialize::display_path;
+use crate::{Cli, Comparison, File, Options};
+
+/// The number of unchanged lines around each change in the diff.
+const CONTEXT_LINES: usize = 3;
+
+/// A single line of the diff, with indexes into the compared lines of the files.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Row {
+    Equal(usize, usize),
+    Removed(usize),
+    Added(usize),
+}
+
+impl Row {
+    fn is_change(self) -> bool {
+        !matches!(self, Self::Equal(..))
+    }
+
+    /// The index of the line in the first file, if the row includes it.
+    fn old_line(self) -> Option<usize> {
+        match self {
+            Self::Equal(i, _) | Self::Removed(i) => Some(i),
+            Self::Added(_) => None,
+        }
+    }
+
+    /// The index of the line in the second file, if the row includes it.
+    fn new_line(self) -> Option<usize> {
+        match self {
+            Self::Equal(_, j) | Self::Added(j) => Some(j),
+            Self::Removed(_) => None,
+        }
+    }
+}
+
+/// Save or print the diffs of the similar pairs, depending on the options.
+pub fn diffs(comparisons: &[Comparison], cli: &Cli, options: &Options) -> Result<()> {
+    if let Some(dir) = &cli.diff_dir {
+        log::debug!("Saving the diffs of similar files…");
+        fs::create_dir_all(dir)?;
+    }
+
+    for (index, comparison) in comparisons.iter().enumerate() {
+        let diff = unified_diff(comparison.file1, comparison.file2, options)?;
+
+        if let Some(dir) = &cli.diff_dir {
+            fs::write(dir.join(diff_file_name(index, comparison)), &diff)?;
+        }
+        log::trace!("Differences between the similar files:\n{diff}");
+    }
+
+    Ok(())
+}
+
+/// Name the diff file after its position in the results and the names of the compared files.
+fn diff_file_name(index: usize, comparison: &Comparison) -> String {
+    let name = |path: &Path| {
+        path.file_name()
+            .map_or_else(String::new, |name| name.to_string_lossy().into_owned())
+    };
+
+    format!(
+        "{:04}-{}--{}.diff",
+        index + 1,
+        name(&comparison.file1.path),
+        name(&comparison.file2.path)
+    )
+}
+
+/// Produce a unified diff of the compared content of the two files.
+///
+/// The diff shows the lines after skipping, extracting the text, replacing, and normalizing,
+/// so that only the differences that count appear in it. The header before the diff
+/// lists the skipped lines of each file. If the compared content isn't the original one,
+/// the header of each hunk also lists the original lines that it comes from.
+fn unified_diff(file1: &File, file2: &File, options: &Options) -> Result<String> {
+    let old: Vec<&str> = file1.content.lines().collect();
+    let new: Vec<&str> = file2.content.lines().collect();
+
+    let rows = rows(&old, &new);
+
+    let mut diff = String::new();
+    for file in [file1, file2] {
+        if !file.skipped.is_empty() {
+            writeln!(
+                diff,
+                "Skipped lines in {}: {}",
+                display_path(file, options),
+                line_ranges(&file.skipped)
+            )?;
+        }
+    }
+    writeln!(diff, "--- {}", display_path(file1, options))?;
+    writeln!(diff, "+++ {}", display_path(file2, options))?;
+
+    let changed = file1.original.is_some() || file2.original.is_some();
+
+    for hunk in hunks(&rows) {
+        let hunk = &rows[hunk.0..hunk.1];
+
+        // The first line of the hunk in each file, counted from 1.
+        // An empty range in unified diffs starts at the line before it.
+        let old_lines: Vec<usize> = hunk.iter().filter_map(|row| row.old_line()).collect();
+        let new_lines: Vec<usize> = hunk.iter().filter_map(|row| row.new_line()).collect();
+        let old_start = old_lines.first().map_or(0, |i| i + 1);
+        let new_start = new_lines.first().map_or(0, |j| j + 1);
+
+        write!(
+            diff,
+            "@@ -{old_start},{} +{new_start},{} @@",
+            old_lines.len(),
+            new_lines.len()
+        )?;
+        if changed {
+            write!(
+                diff,
+                " original lines {} and {}",
+                original_ranges(file1, &old_lines),
+                original_ranges(file2, &new_lines)
+            )?;
+        }
+        writeln!(diff)?;
+
+        for row in hunk {
+            match *row {
+                Row::Equal(i, _) => writeln!(diff, " {}", old[i])?,
+                Row::Removed(i) => writeln!(diff, "-{}", old[i])?,
+                Row::Added(j) => writeln!(diff, "+{}", new[j])?,
+            }
+        }
+    }
+
+    Ok(diff)
+}
+
+/// The original lines that these lines of the compared content come from, as ranges.
+fn original_ranges(file: &File, lines: &[usize]) -> String {
+    let mut origins: Vec<usize> = lines.iter().map(|&line| file.origin(line)).collect();
+    origins.sort_unstable();
+    origins.dedup();
+
+    if origins.is_empty() {
+        "none".to_string()
+    } else {
+        line_ranges(&origins)
+    }
+}
+
+/// Present the indexes of the lines as line numbers, with consecutive lines as ranges,
+/// such as `2, 5-7`.
+fn line_ranges(lines: &[usize]) -> String {
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+
+    for &line in lines {
+        match ranges.last_mut() {
+            Some(last) if last.1 + 1 == line => last.1 = line,
+            _ => ranges.push((line, line)),
+        }
+    }
+
+    ranges
+        .iter()
+        .map(|&(first, last)| {
+            if first == last {
+                (first + 1).to_string()
+            } else {
+                format!("{}-{}", first + 1, last + 1)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Diff the compared lines of the files.
+fn rows(old: &[&str], new: &[&str]) -> Vec<Row> {
+    let mut rows = Vec::new();
+
+    for op in capture_diff_slices(Algorithm::Myers, old, new) {
+        match op.tag() {
+            DiffTag::Equal => {
+                rows.extend(
+                    op.old_range()
+                        .zip(op.new_range())
+                        .map(|(i, j)| Row::Equal(i, j)),
+                );
+            }
+            DiffTag::Delete | DiffTag::Insert | DiffTag::Replace => {
+                rows.extend(op.old_range().map(Row::Removed));
+                rows.extend(op.new_range().map(Row::Added));
+            }
+        }
+    }
+
+    rows
+}
+
+/// Find the ranges of rows that form the hunks of the diff: the changes
+/// with their surrounding context, merged where they overlap.
+fn hunks(rows: &[Row]) -> Vec<(usize, usize)> {
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+
+    for (index, row) in rows.iter().enumerate() {
+        if !row.is_change() {
+            continue;
+        }
+
+        let start = index.saturating_sub(CONTEXT_LINES);
+        let end = (index + 1 + CONTEXT_LINES).min(rows.len());
+
+        match hunks.last_mut() {
+            Some(last) if last.1 >= start => last.1 = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+
+    hunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn valid_unified_diff() {
+        let file1 = File::with_content("a.txt", "A\nB\nC\nD\nE");
+        let file2 = File::with_content("b.txt", "A\nB\nC\nD\nF\nG");
+        let options = Options {
+            path: vec!["".into()],
+            ..Options::default()
+        };
+
+        assert_eq!(
+            "--- a.txt\n\
+            +++ b.txt\n\
+            @@ -2,4 +2,5 @@\n \
+            B\n \
+            C\n \
+            D\n\
+            -E\n\
+            +F\n\
+            +G\n",
+            unified_diff(&file1, &file2, &options).unwrap()
+        );
+    }
+
+    #[test]
+    fn compared_lines_with_original_numbers() {
+        // The first file skips two comments, and the second file one.
+        let mut file1 = File::with_content("a.txt", "A\n// one\n// two\nB\nC");
+        file1.remove_lines("A\nB\nC".to_string(), &[1, 2]);
+        let mut file2 = File::with_content("b.txt", "A\n// three\nB\nD");
+        file2.remove_lines("A\nB\nD".to_string(), &[1]);
+        // The normalization doesn't count as a change.
+        file2.set_content("a\nb\nd".to_string());
+        file1.set_content("a\nb\nc".to_string());
+
+        let options = Options {
+            path: vec!["".into()],
+            ..Options::default()
+        };
+
+        assert_eq!(
+            "Skipped lines in a.txt: 2-3\n\
+            Skipped lines in b.txt: 2\n\
+            --- a.txt\n\
+            +++ b.txt\n\
+            @@ -1,3 +1,3 @@ original lines 1, 4-5 and 1, 3-4\n \
+            a\n \
+            b\n\
+            -c\n\
+            +d\n",
+            unified_diff(&file1, &file2, &options).unwrap()
+        );
+    }
+
+    #[test]
+    fn joined_lines_map_to_their_origin() {
+        let mut file = File::with_content("a.txt", "Title\n\nOne\ntwo.\n\nThree\nfour.\nEnd");
+        file.set_content("Title\n\nOne two.\n\nThree four.\nEnd".to_string());
+
+        let origins: Vec<usize> = (0..6).map(|line| file.origin(line)).collect();
+        assert_eq!(vec![0, 1, 2, 4, 5, 7], origins);
+    }
+}
@@ -24,57 +24,377 @@ limitations under the License.
 // Forbid unsafe code in this program.
 #![forbid(unsafe_code)]
 
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
 use color_eyre::{eyre::bail, Result};
 use permutator::Combination;
+use similar::{capture_diff_slices_deadline, Algorithm};
 
+mod archives;
+mod asciidoc;
+mod blocks;
+mod boilerplate;
 pub mod cli;
+mod clusters;
+mod code;
 mod comparison;
+mod config;
+mod diff;
 mod load_files;
 mod logging;
+mod markdown;
+mod markup;
+mod normalize;
+mod options;
 mod percentage;
+mod report;
+mod reporter;
+pub mod results;
+mod rules;
+mod selection;
 mod serialize;
+mod tfidf;
+mod tokens;
 
-use cli::Cli;
+use boilerplate::handle_boilerplate;
+pub use boilerplate::Boilerplate;
+use cli::{Cli, Metric};
 use comparison::{comparisons, Comparison};
+pub use config::load_config;
+use diff::diffs;
 use load_files::files;
+pub use load_files::{Corpus, Skipped};
 pub use logging::init_log_and_errors;
-use serialize::serialize;
+pub use options::Options;
+pub use report::{Report, Timing};
+use reporter::{log_report, log_selection};
+use results::Results;
+pub use selection::Reason;
+use serialize::{build_results, serialize};
+use tokens::tokenize_files;
 
 /// Represents a loaded text file, with its path and content.
 #[derive(Debug)]
 pub struct File {
     pub path: PathBuf,
     pub content: String,
+    /// The content as loaded, before skipping lines and normalizing it. Only stored
+    /// if it differs from the compared content.
+    pub original: Option<String>,
+    /// The index of the root directory in the `--path` options that contains the file.
+    pub root: usize,
+    /// The indexes of the original lines that the comparison skips.
+    pub skipped: Vec<usize>,
+    /// The index of the original line that each line of the compared content comes from.
+    /// Empty if the lines are the original ones.
+    pub origins: Vec<usize>,
+    /// Which part of the file the compared content is. Only the same parts are compared.
+    pub part: Part,
+    /// The similarity threshold for this file, either global or from a matching rule.
+    pub threshold: f64,
+    /// The comparison metric for this file, either global or from a matching rule.
+    pub metric: Metric,
+    /// The size of the file in bytes as stored, before decoding. For a file in an archive,
+    /// the size of the unpacked entry.
+    pub bytes: usize,
+    /// The number of lines in the file, including the skipped lines.
+    pub lines: usize,
+    /// The content as token IDs, unless the comparison works on characters.
+    pub tokens: Vec<u32>,
+    /// The TF-IDF weights of the tokens, sorted by the token ID, if any file
+    /// uses the TF-IDF metric.
+    pub weights: Vec<(u32, f64)>,
 }
 
-pub fn run(options: &Cli) -> Result<()> {
-    // Check that the similarity threshold is a valid percentage between 0% and 100%.
-    // The value is stored as a decimal between 0 and 1, but it's exposed to the user
-    // as a value between 0 and 100.
-    if options.threshold < 0.0 || options.threshold > 1.0 {
-        bail!("The similarity threshold must be between 0.0 and 100.0.")
+/// The part of a file that forms the compared content.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Part {
+    /// The text of the file, which is the prose with `--markdown-code separate`.
+    #[default]
+    Text,
+    /// The code blocks of a Markdown file with `--markdown-code separate`.
+    CodeBlocks,
+}
+
+impl File {
+    /// The path of the file relative to its root directory.
+    #[must_use]
+    pub fn relative_path(&self, options: &Options) -> &Path {
+        self.path
+            .strip_prefix(&options.path[self.root])
+            .unwrap_or(&self.path)
+    }
+
+    /// The content of the file as loaded, before skipping any lines.
+    #[must_use]
+    pub fn original(&self) -> &str {
+        self.original.as_deref().unwrap_or(&self.content)
+    }
+
+    /// The index of the original line that this line of the compared content comes from.
+    #[must_use]
+    pub fn origin(&self, line: usize) -> usize {
+        self.origins.get(line).copied().unwrap_or(line)
+    }
+
+    /// Replace the compared content, and keep the content as loaded for the diffs.
+    /// Each new line maps to the original line that it most likely comes from.
+    fn set_content(&mut self, content: String) {
+        self.origins = line_sources(&self.content, &content)
+            .into_iter()
+            .map(|line| self.origin(line))
+            .collect();
+
+        let previous = std::mem::replace(&mut self.content, content);
+        self.original.get_or_insert(previous);
+    }
+
+    /// Remove these lines of the compared content, which is then the new content.
+    /// The removed lines count as skipped.
+    fn remove_lines(&mut self, content: String, removed: &[usize]) {
+        let skipped: Vec<usize> = removed.iter().map(|&line| self.origin(line)).collect();
+        self.skipped.extend(skipped);
+        self.skipped.sort_unstable();
+        self.skipped.dedup();
+
+        self.origins = (0..self.content.lines().count())
+            .filter(|line| removed.binary_search(line).is_err())
+            .map(|line| self.origin(line))
+            .collect();
+
+        let previous = std::mem::replace(&mut self.content, content);
+        self.original.get_or_insert(previous);
+    }
+}
+
+/// For each line of the new text, find the line of the old text that it most likely comes from.
+/// The unchanged lines map exactly, and the changed lines proportionally within each change.
+fn line_sources(old: &str, new: &str) -> Vec<usize> {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+
+    // Most changes, such as the normalization of case, keep each line in its place.
+    // Comparing the lines would only find that they all differ.
+    if old.len() == new.len() {
+        return (0..new.len()).collect();
+    }
+
+    // A long diff of a large file isn't worth the wait. After the deadline,
+    // the remaining lines form a single change.
+    let deadline = Instant::now() + Duration::from_secs(1);
+    let last = old.len().saturating_sub(1);
+    let mut sources = Vec::with_capacity(new.len());
+
+    for op in capture_diff_slices_deadline(Algorithm::Myers, &old, &new, Some(deadline)) {
+        let (old_range, new_range) = (op.old_range(), op.new_range());
+
+        for index in 0..new_range.len() {
+            // Added lines belong to the line before them.
+            let source = if old_range.is_empty() {
+                old_range.start.saturating_sub(1)
+            } else {
+                old_range.start + index * old_range.len() / new_range.len()
+            };
+            sources.push(source.min(last));
+        }
+    }
+
+    sources
+}
+
+#[cfg(test)]
+impl File {
+    /// A file with the content and the default settings, for the tests.
+    pub(crate) fn with_content(path: &str, content: &str) -> Self {
+        let options = Options::default();
+
+        Self {
+            path: PathBuf::from(path),
+            content: content.to_string(),
+            original: None,
+            root: 0,
+            skipped: Vec::new(),
+            origins: Vec::new(),
+            part: Part::Text,
+            threshold: options.threshold,
+            metric: options.metric,
+            bytes: content.len(),
+            lines: content.lines().count(),
+            tokens: Vec::new(),
+            weights: Vec::new(),
+        }
+    }
+}
+
+/// Create a temporary directory with these files for a test, and return its path.
+#[cfg(test)]
+pub(crate) fn test_dir(name: &str, files: &[(&str, &[u8])]) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("near-facsimile-{name}-{}", std::process::id()));
+    // The directory only remains if an earlier run failed.
+    let _ = std::fs::remove_dir_all(&dir);
+
+    for (path, content) in files {
+        let path = dir.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
     }
 
+    dir
+}
+
+/// Load and prepare the files for the comparison.
+///
+/// # Errors
+///
+/// Fails if the options are invalid, or if the files cannot be loaded.
+pub fn load_corpus(options: &Options) -> Result<Corpus> {
+    options.validate()?;
+
+    let mut corpus = files(options, false)?;
+    prepare(&mut corpus, options)?;
+
+    Ok(corpus)
+}
+
+/// Compare the loaded files, and return the similar pairs, from the most similar,
+/// and the skipped files.
+///
+/// ```no_run
+/// use near_facsimile::{compare, load_corpus, Options};
+///
+/// let options = Options::default();
+/// let corpus = load_corpus(&options)?;
+/// let results = compare(&corpus, &options)?;
+///
+/// for pair in &results.comparisons {
+///     println!("{:.1}%: {} {}", pair.pct_similar, pair.file1, pair.file2);
+/// }
+/// # Ok::<(), color_eyre::Report>(())
+/// ```
+///
+/// # Errors
+///
+/// Fails if the options are invalid.
+pub fn compare(corpus: &Corpus, options: &Options) -> Result<Results> {
+    options.validate()?;
+
+    let comparisons = similar_pairs(&corpus.files, options, false);
+
+    build_results(&comparisons, corpus.files.len(), &corpus.skipped, options)
+}
+
+/// Load and compare the files, and return the report with the similar pairs,
+/// their clusters, the skipped files, and how long each stage took.
+///
+/// # Errors
+///
+/// Fails if the options are invalid, or if the files cannot be loaded.
+pub fn find_similar(options: &Options) -> Result<Report> {
+    options.validate()?;
+
+    let start = Instant::now();
+    let mut corpus = files(options, false)?;
+    let loading = start.elapsed();
+
+    let (_, report) = analyze(&mut corpus, options, false, loading)?;
+
+    Ok(report)
+}
+
+/// Prepare and compare the loaded files, and report the similar pairs with the time
+/// that each stage took. Also returns the comparisons, which refer to the files.
+fn analyze<'a>(
+    corpus: &'a mut Corpus,
+    options: &Options,
+    progress: bool,
+    loading: Duration,
+) -> Result<(Vec<Comparison<'a>>, Report)> {
+    let mut timing = Timing {
+        loading,
+        ..Timing::default()
+    };
+
+    let start = Instant::now();
+    prepare(corpus, options)?;
+    timing.preparing = start.elapsed();
+
+    let corpus: &Corpus = corpus;
+    let start = Instant::now();
+    let comparisons = similar_pairs(&corpus.files, options, progress);
+    timing.comparing = start.elapsed();
+
+    let report = Report::new(&comparisons, corpus, options, timing)?;
+
+    Ok((comparisons, report))
+}
+
+/// Process the content that depends on all the loaded files, rather than on each file.
+fn prepare(corpus: &mut Corpus, options: &Options) -> Result<()> {
+    corpus.boilerplate = handle_boilerplate(&mut corpus.files, options);
+    tokenize_files(&mut corpus.files, options)?;
+    tfidf::weigh_files(&mut corpus.files);
+
+    Ok(())
+}
+
+/// Compare the pairs of files, and keep those that are more similar than the threshold.
+fn similar_pairs<'a>(files: &'a [File], options: &Options, progress: bool) -> Vec<Comparison<'a>> {
+    if files.iter().all(|file| file.metric == Metric::Tfidf) {
+        // The TF-IDF metric only compares the files that share a weighted word.
+        let candidates = tfidf::candidates(files);
+        comparisons(
+            candidates.into_iter().map(|(i, j)| (&files[i], &files[j])),
+            options,
+            progress,
+        )
+    } else {
+        // Combinations by 2 pair each file with each file, so that no comparison
+        // occurs more than once.
+        let combinations = files.combination(2).map(|v| (v[0], v[1]));
+        comparisons(combinations, options, progress)
+    }
+}
+
+/// Load the files, compare them, and report the results according to the options.
+/// Returns the report, or nothing if the dry run only lists the files.
+///
+/// # Errors
+///
+/// Fails if the options are invalid, if the files cannot be loaded,
+/// or if the results cannot be saved.
+pub fn run(cli: &Cli) -> Result<Option<Report>> {
+    let options = Options::from(cli);
+    options.validate()?;
+
     // Load all matching files from the directory.
-    let files = files(options)?;
+    let start = Instant::now();
+    let mut corpus = files(&options, cli.list_files)?;
+    let loading = start.elapsed();
+    log_selection(&corpus, cli);
+
+    // The dry run only lists the files.
+    if cli.list_files {
+        return Ok(None);
+    }
 
     // The comparison needs at least two files.
-    if files.len() < 2 {
+    if corpus.files.len() < 2 {
         bail!("Too few files that match the settings to compare in this directory.");
     }
 
-    // Combinations by 2 pair each file with each file, so that no comparison
-    // occurs more than once.
-    let combinations = files.combination(2).map(|v| (v[0], v[1]));
+    let (comparisons, report) = analyze(&mut corpus, &options, cli.progress, loading)?;
+    log_report(&report, &options);
 
-    let comparisons = comparisons(combinations, options);
+    // Only prepare the diffs if the user wants to see them.
+    if cli.diff_dir.is_some() || cli.verbose > 1 {
+        diffs(&comparisons, cli, &options)?;
+    }
 
     // Only serialize if at least one serialization options is active.
-    if options.csv.is_some() || options.json.is_some() {
-        serialize(comparisons, options)?;
+    if cli.csv.is_some() || cli.json.is_some() || cli.markdown.is_some() || cli.junit.is_some() {
+        serialize(&report, cli, &options)?;
     }
 
-    Ok(())
+    Ok(Some(report))
 }
@@ -14,176 +14,755 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
-use std::ffi::OsStr;
+use std::borrow::Cow;
+use std::collections::HashSet;
 use std::fs;
-use std::io;
+use std::io::{self, Read};
 use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 
+use chardetng::EncodingDetector;
+use color_eyre::eyre::{bail, WrapErr};
 use color_eyre::Result;
-use ignore::Walk;
+use encoding_rs::Encoding;
+use ignore::{WalkBuilder, WalkState};
 use regex::Regex;
 
-use crate::{Cli, File};
+use crate::archives::{virtual_path, Archive};
+use crate::asciidoc::AsciiDoc;
+use crate::blocks::blocked_lines;
+use crate::boilerplate::Boilerplate;
+use crate::cli::{Format, Language};
+use crate::code::Tokenizer;
+use crate::markdown::{self, code_path};
+use crate::markup;
+use crate::normalize::normalize;
+use crate::rules::Rules;
+use crate::selection::{Reason, Selection, Selector};
+use crate::{File, Options, Part};
+
+/// The name of the files that list the paths to skip, in the gitignore syntax.
+/// Unlike `.gitignore`, they only affect this tool.
+const IGNORE_FILE_NAME: &str = ".nfignore";
+
+/// How many bytes at the start of a file to check for binary data before reading the rest.
+const BINARY_CHECK_LENGTH: u64 = 8 * 1024;
+
+/// The loaded files, and the files that were skipped.
+#[derive(Debug)]
+pub struct Corpus {
+    pub files: Vec<File>,
+    pub skipped: Vec<Skipped>,
+    /// The lines and paragraphs that many files share, with `--boilerplate`.
+    /// Found when the files are prepared for the comparison.
+    pub boilerplate: Vec<Boilerplate>,
+    /// The decision about each visited file, as the dry run lists it.
+    pub(crate) listing: Vec<String>,
+}
+
+/// A file that doesn't take part in the comparison.
+#[derive(Debug)]
+pub struct Skipped {
+    pub path: PathBuf,
+    /// The index of the root directory of the file.
+    pub root: usize,
+    pub reason: Reason,
+}
 
 /// Load files and filter out those that are ignored by the comparisons.
-pub fn files(options: &Cli) -> Result<Vec<File>> {
+/// The dry run only decides which files to compare from their paths and sizes,
+/// without reading them.
+pub fn files(options: &Options, dry_run: bool) -> Result<Corpus> {
     log::debug!("Loading files…");
 
-    let base_path = &options.path;
+    let rules = Rules::new(options)?;
+    let selector = Selector::new(options, &rules)?;
+
+    let asciidoc = AsciiDoc::new(options)?;
 
-    let files = visit_dirs(base_path, options)?;
+    let mut loader = Loader {
+        rules: &rules,
+        selector: &selector,
+        asciidoc: &asciidoc,
+        encoding: configured_encoding(options)?,
+        seen: HashSet::new(),
+        files: Vec::new(),
+        skipped: Vec::new(),
+        listing: Vec::new(),
+        options,
+        dry_run,
+    };
 
-    // If the "skip-lines" option is not set, return files as they are.
-    if options.skip_lines.is_empty() {
-        Ok(files)
-    // If the "skip-lines" option is set, remove all lines that match the regular
-    // expressions from the file contents, before returning them.
+    // Either load the files from the list, or search the root directories.
+    if let Some(list) = &options.files_from {
+        for path in listed_paths(list, io::stdin())? {
+            loader.visit_listed(&path)?;
+        }
     } else {
-        Ok(files
-            .into_iter()
-            .map(|file| File {
-                content: strip_lines(&file.content, &options.skip_lines),
-                ..file
-            })
-            .collect())
+        for (root, dir) in options.path.iter().enumerate() {
+            loader.visit_dir(root, dir)?;
+        }
     }
+
+    Ok(Corpus {
+        files: loader.files,
+        skipped: loader.skipped,
+        boilerplate: Vec::new(),
+        listing: loader.listing,
+    })
 }
 
-/// Recursively load all files in this directory as a Vec.
-fn visit_dirs(dir: &Path, options: &Cli) -> Result<Vec<File>> {
-    let mut files = Vec::new();
-    let walk = Walk::new(dir);
+/// Collects the files that match the options.
+struct Loader<'a> {
+    rules: &'a Rules<'a>,
+    selector: &'a Selector<'a>,
+    asciidoc: &'a AsciiDoc<'a>,
+    /// The encoding of all files, or `None` to detect it in each file.
+    encoding: Option<&'static Encoding>,
+    /// The canonical paths of the loaded files. If the root directories overlap,
+    /// each file is only loaded once.
+    seen: HashSet<PathBuf>,
+    files: Vec<File>,
+    skipped: Vec<Skipped>,
+    listing: Vec<String>,
+    options: &'a Options,
+    dry_run: bool,
+}
+
+/// The outcome of examining a single file.
+struct Visit {
+    path: PathBuf,
+    root: usize,
+    /// The canonical path, which identifies the file even if the root directories overlap.
+    key: PathBuf,
+    selection: Selection,
+    /// The loaded file, if it's included.
+    file: Option<File>,
+}
+
+impl Loader<'_> {
+    /// Recursively load all files in this directory. The directory is searched
+    /// in parallel, and the files are then added in the order of their paths.
+    fn visit_dir(&mut self, root: usize, dir: &Path) -> Result<()> {
+        let visits: Mutex<Vec<Result<Vec<Visit>>>> = Mutex::new(Vec::new());
+
+        walker(dir, self.options).build_parallel().run(|| {
+            let visits = &visits;
+            let this = &*self;
+            Box::new(move |entry| {
+                let visit = match entry {
+                    Ok(entry) if entry.path().is_file() => this.visit(root, dir, entry.path()),
+                    Ok(_) => return WalkState::Continue,
+                    Err(e) => Err(e.into()),
+                };
+                let failed = visit.is_err();
+                visits
+                    .lock()
+                    .expect("Another thread panicked while loading files.")
+                    .push(visit);
+
+                if failed {
+                    WalkState::Quit
+                } else {
+                    WalkState::Continue
+                }
+            })
+        });
+
+        let mut visits = visits
+            .into_inner()
+            .expect("Another thread panicked while loading files.")
+            .into_iter()
+            .collect::<Result<Vec<_>>>()?
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>();
+        visits.sort_by(|v1, v2| v1.path.cmp(&v2.path));
+
+        for visit in visits {
+            self.add(visit);
+        }
+
+        Ok(())
+    }
 
-    for entry in walk {
-        let entry = entry?;
-        let path = entry.path();
+    /// Load a file from the `--files-from` list. The file belongs to the first root directory
+    /// that contains it, or to the first root directory if none does.
+    fn visit_listed(&mut self, path: &Path) -> Result<()> {
+        if !path.is_file() {
+            log::warn!("Skipping listed path that isn't a file: {}", path.display());
+            return Ok(());
+        }
+
+        let root = self
+            .options
+            .path
+            .iter()
+            .position(|dir| path.starts_with(dir))
+            .unwrap_or(0);
+
+        for visit in self.visit(root, &self.options.path[root], path)? {
+            self.add(visit);
+        }
 
-        if path.is_file() && wanted(path, options) {
-            if let Some(file) = load_file(entry.into_path())? {
-                files.push(file);
+        Ok(())
+    }
+
+    /// Decide whether the file takes part in the comparison, and if so, load it.
+    /// With `--archives`, the files inside an archive are visited instead of the archive.
+    fn visit(&self, root: usize, dir: &Path, path: &Path) -> Result<Vec<Visit>> {
+        let relative = path.strip_prefix(dir).unwrap_or(path);
+        let key = path.canonicalize()?;
+
+        if self.options.archives {
+            if let Some(archive) = Archive::from_path(path) {
+                return Ok(self.visit_archive(root, path, relative, &key, archive));
             }
         }
+
+        let size = path
+            .metadata()
+            .wrap_err_with(|| format!("Failed to read the file: {}", path.display()))?
+            .len();
+
+        self.visit_one(root, path.to_path_buf(), relative, key, size, || {
+            load_file(path.to_path_buf(), self.encoding, self.options)
+        })
     }
 
-    Ok(files)
-}
+    /// Visit each file in the archive, with a virtual path such as `drop.tar.gz!/intro.adoc`.
+    fn visit_archive(
+        &self,
+        root: usize,
+        path: &Path,
+        relative: &Path,
+        key: &Path,
+        archive: Archive,
+    ) -> Vec<Visit> {
+        // Excluding the archive excludes all the files inside it.
+        if let Some(reason) = self.selector.excluded(path, relative) {
+            return vec![Visit {
+                path: path.to_path_buf(),
+                root,
+                key: key.to_path_buf(),
+                selection: Selection::Skipped(reason),
+                file: None,
+            }];
+        }
 
-/// Load the content of a file as `File`, if the file is valid UTF-8 text.
-/// Returns `Ok(None)` if the file is accessible but not text.
-fn load_file(path: PathBuf) -> Result<Option<File>> {
-    log::debug!("Loading file: {:?}", &path);
-    match fs::read_to_string(&path) {
-        // If the file is UTF-8 text, add it to the list of files.
-        Ok(content) => Ok(Some(File { path, content })),
-        // If we can't read the file:
-        Err(e) => {
-            // If we can't read it because it's not UTF-8, just skip the file.
-            if e.kind() == io::ErrorKind::InvalidData {
-                log::debug!(
-                    "Skipping file that is not valid UTF-8 text: {}",
-                    path.display()
+        log::debug!("Reading archive: {}", path.display());
+        let mut visits = Vec::new();
+
+        let read = archive.for_each_file(path, |inner, size, reader| {
+            let virtual_file = virtual_path(path, inner);
+            let load = || -> Result<Result<File, Reason>> {
+                let mut bytes = Vec::new();
+                reader.read_to_end(&mut bytes)?;
+                Ok(text_file(
+                    virtual_file.clone(),
+                    &bytes,
+                    self.encoding,
+                    self.options,
+                ))
+            };
+
+            let parts = self.visit_one(
+                root,
+                virtual_file.clone(),
+                &virtual_path(relative, inner),
+                virtual_path(key, inner),
+                size,
+                load,
+            )?;
+            visits.extend(parts);
+            Ok(())
+        });
+
+        // A corrupt or truncated archive doesn't stop the comparison of the other files.
+        // None of its files take part, even those that were read before the error.
+        if let Err(error) = read {
+            log::warn!("{error:#}");
+            return vec![Visit {
+                path: path.to_path_buf(),
+                root,
+                key: key.to_path_buf(),
+                selection: Selection::Skipped(Reason::UnreadableArchive),
+                file: None,
+            }];
+        }
+
+        visits
+    }
+
+    /// Select the file by its path and size, and if it's included, load it with the function.
+    /// A loaded file can result in several compared parts, such as the prose
+    /// and the code blocks of a Markdown file.
+    fn visit_one<F>(
+        &self,
+        root: usize,
+        path: PathBuf,
+        relative: &Path,
+        key: PathBuf,
+        size: u64,
+        load: F,
+    ) -> Result<Vec<Visit>>
+    where
+        F: FnOnce() -> Result<Result<File, Reason>>,
+    {
+        let skipped = |path, key, selection| {
+            Ok(vec![Visit {
+                path,
+                root,
+                key,
+                selection,
+                file: None,
+            }])
+        };
+
+        let reason = match self.selector.select(&path, relative) {
+            Selection::Included(reason) => reason,
+            selection @ Selection::Skipped(_) => return skipped(path, key, selection),
+        };
+
+        // Check the size before reading anything.
+        if let Err(reason) = check_size(size, self.options) {
+            return skipped(path, key, Selection::Skipped(reason));
+        }
+
+        // The dry run can't tell whether the content is suitable without reading it.
+        if self.dry_run {
+            return skipped(path, key, Selection::Included(reason));
+        }
+
+        // Files that aren't text are skipped after loading them.
+        let mut file = match load()? {
+            Ok(file) => file,
+            Err(reason) => return skipped(path, key, Selection::Skipped(reason)),
+        };
+        file.root = root;
+
+        let visits = self
+            .prepare(file, relative)
+            .into_iter()
+            .map(|file| {
+                // The only other part is the code blocks of a Markdown file.
+                let part_path = file.path.clone();
+                let key = if part_path == path {
+                    key.clone()
+                } else {
+                    code_path(&key)
+                };
+
+                // Files that are too short are skipped after preparing their content.
+                let (selection, file) = match too_short(&file, self.options) {
+                    Some(reason) => (Selection::Skipped(reason), None),
+                    None => (Selection::Included(reason.clone()), Some(file)),
+                };
+
+                Visit {
+                    path: part_path,
+                    root,
+                    key,
+                    selection,
+                    file,
+                }
+            })
+            .collect();
+
+        Ok(visits)
+    }
+
+    /// Apply the rules that match the file, skip the configured lines and blocks,
+    /// extract the text from the markup, and substitute and normalize the remaining content.
+    /// Returns the file, and with `--markdown-code separate`, its code blocks as another file.
+    fn prepare(&self, mut file: File, relative: &Path) -> Vec<File> {
+        let options = self.options;
+        let rules = self.rules;
+        let format = options.format.of_file(&file.path);
+        rules.apply(&mut file, relative);
+
+        // Skip the lines set by the "skip-lines" option and by the rules that match the file.
+        let regexes: Vec<Regex> = options
+            .skip_lines
+            .iter()
+            .chain(rules.skip_lines(relative))
+            .cloned()
+            .collect();
+
+        // The lines in the blocks set by the "skip-block" option,
+        // and in the AsciiDoc format, the comments and other skipped markup.
+        let mut blocked = blocked_lines(&file.content, &options.skip_block);
+        if format == Format::AsciiDoc {
+            blocked.extend(self.asciidoc.skipped_lines(&file.content));
+            blocked.sort_unstable();
+            blocked.dedup();
+        }
+
+        // Remove all lines that match the regular expressions or that belong to a block
+        // from the compared content.
+        if !regexes.is_empty() || !blocked.is_empty() {
+            let (content, skipped) = strip_lines(&file.content, &regexes, &blocked);
+            file.remove_lines(content, &skipped);
+        }
+
+        // Resolve the AsciiDoc attributes and includes in the remaining content.
+        if format == Format::AsciiDoc {
+            let content = self.asciidoc.expand(&file.content, &file.path);
+            if content != file.content {
+                file.set_content(content);
+            }
+        }
+
+        // Extract the text from the Markdown markup. The code blocks can form another file.
+        let mut files = Vec::new();
+        if format == Format::Markdown {
+            let extracted = markdown::extract(&file.content, options.markdown_code);
+            if let Some(code) = extracted.code {
+                // The lines of the code blocks keep their line numbers in the Markdown file.
+                let mut part = File {
+                    path: code_path(&file.path),
+                    content: file.content.clone(),
+                    original: file.original.clone(),
+                    skipped: file.skipped.clone(),
+                    origins: file.origins.clone(),
+                    part: Part::CodeBlocks,
+                    tokens: Vec::new(),
+                    weights: Vec::new(),
+                    ..file
+                };
+                part.set_content(code);
+                files.push(part);
+            }
+            file.set_content(extracted.prose);
+        }
+
+        // Extract the text nodes from the HTML or XML markup.
+        if matches!(format, Format::Html | Format::Xml) {
+            let html = format == Format::Html;
+            let content = markup::extract(&file.content, html, options.markup_structure);
+            file.set_content(content);
+        }
+
+        // Tokenize the source code. Files in unknown languages stay plain text.
+        if format == Format::Code {
+            if let Some(language) = Language::from_path(&file.path) {
+                let tokenizer = Tokenizer::new(
+                    language,
+                    options.code_rename_identifiers,
+                    options.code_rename_literals,
                 );
-                Ok(None)
-            // If we can't read it for any other reason, exit with the contained error.
+                let content = tokenizer.tokenize(&file.content);
+                file.set_content(content);
             } else {
-                Err(e)?
+                log::debug!(
+                    "Comparing a file in an unknown language as plain text: {}",
+                    file.path.display()
+                );
             }
         }
+        files.insert(0, file);
+
+        for file in &mut files {
+            self.transform(file);
+        }
+
+        files
     }
-}
 
-/// Determine whether to include this file in the comparison or skip it,
-/// based on the configured requires and ignores.
-fn wanted(path: &Path, options: &Cli) -> bool {
-    if !options.require_file.is_empty() {
-        if !options.require_ext.is_empty() {
-            required_file_name(path, options) || required_extension(path, options)
-        } else {
-            required_file_name(path, options)
+    /// Apply the substitutions and the normalization to the compared content.
+    fn transform(&self, file: &mut File) {
+        let options = self.options;
+
+        // Apply the substitutions in order, each to the result of the previous one.
+        if !options.replace.is_empty() {
+            let content = options
+                .replace
+                .iter()
+                .fold(file.content.clone(), |content, replace| {
+                    replace
+                        .regex
+                        .replace_all(&content, replace.replacement.as_str())
+                        .into_owned()
+                });
+            file.set_content(content);
         }
-    } else if !options.ignore_file.is_empty() {
-        if !options.require_ext.is_empty() {
-            required_extension(path, options) && !ignored_file_name(path, options)
-        } else if !options.ignore_ext.is_empty() {
-            !ignored_file_name(path, options) && !ignored_extension(path, options)
-        } else {
-            !ignored_file_name(path, options)
+
+        if !options.normalize.is_empty() {
+            let content = normalize(&file.content, &options.normalize);
+            file.set_content(content);
         }
-    } else if !options.require_ext.is_empty() {
-        required_extension(path, options)
-    } else if !options.ignore_ext.is_empty() {
-        !ignored_extension(path, options)
-    } else {
-        true
     }
+
+    /// Add the visited file to the collected files, unless it's already loaded.
+    fn add(&mut self, visit: Visit) {
+        let path = &visit.path;
+
+        if !self.seen.insert(visit.key) {
+            log::debug!("Skipping file that's already visited: {}", path.display());
+            return;
+        }
+
+        self.listing
+            .push(format!("{}: {}", visit.selection, path.display()));
+
+        match visit.selection {
+            Selection::Included(_) => self.files.extend(visit.file),
+            Selection::Skipped(reason) => {
+                log::debug!("Skipping file ({reason}): {}", path.display());
+                self.skipped.push(Skipped {
+                    path: visit.path,
+                    root: visit.root,
+                    reason,
+                });
+            }
+        }
+    }
+}
+
+/// Configure the directory search according to the options.
+fn walker(dir: &Path, options: &Options) -> WalkBuilder {
+    let mut builder = WalkBuilder::new(dir);
+
+    builder
+        .standard_filters(!options.no_ignore)
+        .hidden(!options.hidden)
+        .follow_links(options.follow_symlinks)
+        .max_depth(options.max_depth)
+        // The tool's own ignore files apply even with `--no-ignore`.
+        .add_custom_ignore_filename(IGNORE_FILE_NAME)
+        // The Git metadata is never worth comparing, even with `--hidden`.
+        .filter_entry(|entry| entry.file_name() != ".git");
+
+    builder
 }
 
-fn required_file_name(path: &Path, options: &Cli) -> bool {
-    let name = path.file_name().map(OsStr::to_os_string);
+/// Read the list of paths from the file, or from the standard input if the file is `-`.
+/// The paths are separated by NUL characters, such as from `find -print0`,
+/// or by new lines.
+fn listed_paths(list: &Path, mut stdin: impl Read) -> Result<Vec<PathBuf>> {
+    let bytes = if list == Path::new("-") {
+        let mut bytes = Vec::new();
+        stdin.read_to_end(&mut bytes)?;
+        bytes
+    } else {
+        fs::read(list)
+            .wrap_err_with(|| format!("Failed to read the list of files: {}", list.display()))?
+    };
 
-    if let Some(name) = name {
-        options.require_file.contains(&name)
+    // The list stays bytes, because the NUL-separated paths don't have to be valid text.
+    let paths: Vec<&[u8]> = if bytes.contains(&0) {
+        bytes.split(|&byte| byte == 0).collect()
     } else {
-        false
+        bytes
+            .split(|&byte| byte == b'\n')
+            .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+            .collect()
+    };
+
+    paths
+        .into_iter()
+        .filter(|path| !path.is_empty())
+        .map(path_from_bytes)
+        .collect()
+}
+
+/// Convert a listed path to `PathBuf`. On Unix, a path can be any bytes.
+#[cfg(unix)]
+// The function must match the fallible version on other systems.
+#[allow(clippy::unnecessary_wraps)]
+fn path_from_bytes(bytes: &[u8]) -> Result<PathBuf> {
+    use std::os::unix::ffi::OsStrExt;
+
+    Ok(PathBuf::from(std::ffi::OsStr::from_bytes(bytes)))
+}
+
+/// Convert a listed path to `PathBuf`. Elsewhere, paths must be valid text.
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: &[u8]) -> Result<PathBuf> {
+    let text = std::str::from_utf8(bytes)
+        .wrap_err("The list of files contains a path that isn't valid UTF-8 text.")?;
+
+    Ok(PathBuf::from(text))
+}
+
+/// Load the content of a file as `File`, if it's text in the configured encoding.
+/// Returns the reason to skip the file if it's accessible but not suitable.
+fn load_file(
+    path: PathBuf,
+    encoding: Option<&'static Encoding>,
+    options: &Options,
+) -> Result<Result<File, Reason>> {
+    log::debug!("Loading file: {}", path.display());
+
+    let read_error = || format!("Failed to read the file: {}", path.display());
+    let mut handle = fs::File::open(&path).wrap_err_with(read_error)?;
+
+    // Check the start of the file for binary data before reading the whole file.
+    let mut bytes = Vec::new();
+    (&mut handle)
+        .take(BINARY_CHECK_LENGTH)
+        .read_to_end(&mut bytes)
+        .wrap_err_with(read_error)?;
+    if looks_binary(&bytes, encoding) {
+        return Ok(Err(Reason::Binary));
     }
+    handle.read_to_end(&mut bytes).wrap_err_with(read_error)?;
+
+    Ok(text_file(path, &bytes, encoding, options))
 }
 
-fn required_extension(path: &Path, options: &Cli) -> bool {
-    let extension = path.extension().map(OsStr::to_os_string);
+/// Create a `File` from the content, if it's text in the configured encoding.
+fn text_file(
+    path: PathBuf,
+    bytes: &[u8],
+    encoding: Option<&'static Encoding>,
+    options: &Options,
+) -> Result<File, Reason> {
+    decode(bytes, encoding).map(|content| File {
+        path,
+        bytes: bytes.len(),
+        lines: content.lines().count(),
+        content,
+        original: None,
+        skipped: Vec::new(),
+        origins: Vec::new(),
+        part: Part::Text,
+        root: 0,
+        threshold: options.threshold,
+        metric: options.metric,
+        tokens: Vec::new(),
+        weights: Vec::new(),
+    })
+}
 
-    if let Some(extension) = extension {
-        options.require_ext.contains(&extension)
+/// Check that the file size is within the `--min-file-size` and `--max-file-size` limits.
+fn check_size(size: u64, options: &Options) -> Result<(), Reason> {
+    if options.max_file_size.is_some_and(|max| size > max) {
+        Err(Reason::TooLarge)
+    } else if options.min_file_size.is_some_and(|min| size < min) {
+        Err(Reason::TooSmall)
     } else {
-        false
+        Ok(())
     }
 }
 
-fn ignored_file_name(path: &Path, options: &Cli) -> bool {
-    let name = path.file_name().map(OsStr::to_os_string);
+/// Text files in the supported encodings don't contain NUL bytes, except in UTF-16,
+/// which either starts with a byte order mark or is the configured encoding.
+fn looks_binary(bytes: &[u8], encoding: Option<&'static Encoding>) -> bool {
+    let utf16 = encoding.is_some_and(|encoding| {
+        encoding == encoding_rs::UTF_16LE || encoding == encoding_rs::UTF_16BE
+    });
 
-    if let Some(name) = name {
-        options.ignore_file.contains(&name)
+    !utf16 && Encoding::for_bom(bytes).is_none() && bytes.contains(&0)
+}
+
+/// Check whether the compared content is shorter than the `--min-lines`
+/// or `--min-words` options.
+fn too_short(file: &File, options: &Options) -> Option<Reason> {
+    if options
+        .min_lines
+        .is_some_and(|min| file.content.lines().count() < min)
+    {
+        Some(Reason::TooFewLines)
+    } else if options
+        .min_words
+        .is_some_and(|min| file.content.split_whitespace().count() < min)
+    {
+        Some(Reason::TooFewWords)
     } else {
-        false
+        None
     }
 }
 
-fn ignored_extension(path: &Path, options: &Cli) -> bool {
-    let extension = path.extension().map(OsStr::to_os_string);
+/// Decode the file content as text, without the byte order mark.
+///
+/// A byte order mark always determines the encoding. Otherwise, the content is decoded
+/// with the configured encoding, or if there's none, as UTF-8 or the detected encoding.
+fn decode(bytes: &[u8], encoding: Option<&'static Encoding>) -> Result<String, Reason> {
+    if let Some((encoding, bom_length)) = Encoding::for_bom(bytes) {
+        return decode_as(&bytes[bom_length..], encoding);
+    }
+
+    if looks_binary(bytes, encoding) {
+        return Err(Reason::Binary);
+    }
 
-    if let Some(extension) = extension {
-        options.ignore_ext.contains(&extension)
-    } else {
-        false
+    match encoding {
+        Some(encoding) => decode_as(bytes, encoding),
+        None => {
+            if let Ok(text) = std::str::from_utf8(bytes) {
+                Ok(text.to_string())
+            } else {
+                let mut detector = EncodingDetector::new();
+                detector.feed(bytes, true);
+                decode_as(bytes, detector.guess(None, false))
+            }
+        }
+    }
+}
+
+/// Decode the content in this encoding, or fail if it contains invalid characters.
+fn decode_as(bytes: &[u8], encoding: &'static Encoding) -> Result<String, Reason> {
+    log::trace!("Decoding as {}", encoding.name());
+
+    encoding
+        .decode_without_bom_handling_and_without_replacement(bytes)
+        .map(Cow::into_owned)
+        .ok_or(Reason::NotDecodable(encoding.name()))
+}
+
+/// Find the encoding from the `--encoding` option, or `None` to detect it in each file.
+fn configured_encoding(options: &Options) -> Result<Option<&'static Encoding>> {
+    if options.encoding == "auto" {
+        return Ok(None);
+    }
+
+    match Encoding::for_label(options.encoding.as_bytes()) {
+        Some(encoding) => Ok(Some(encoding)),
+        None => bail!("Unknown encoding: {}", options.encoding),
     }
 }
 
-/// Remove all lines that match any specified regular expression from the text.
-fn strip_lines(text: &str, regexes: &[Regex]) -> String {
+/// Remove all lines that match any specified regular expression from the text,
+/// and the blocked lines at the given indexes.
+/// Returns the remaining text and the indexes of the removed lines.
+fn strip_lines(text: &str, regexes: &[Regex], blocked: &[usize]) -> (String, Vec<usize>) {
+    let mut skipped = Vec::new();
+
     let lines: Vec<&str> = text
         .lines()
+        .enumerate()
         // The filter uses the "not any" condition, or `!regexes.iter().any(...)`.
         // That is, if any regex matches the line, the filter for that line
         // evaluates to `false`, and in effect removes the line from the text.
-        .filter(|line| !{
+        .filter(|(index, line)| !{
+            // The blocked lines are removed regardless of the regexes.
+            if blocked.binary_search(index).is_ok() {
+                log::debug!("Skipping line in a block:\n{:?}", &line);
+                skipped.push(*index);
+                return false;
+            }
             regexes.iter().any(|regex| {
                 // Add an `if` block here just so that it can produce a log message.
                 if regex.is_match(line) {
                     log::debug!("Skipping line due to regex {:?}:\n{:?}", &regex, &line);
+                    skipped.push(*index);
                     true
                 } else {
                     false
                 }
             })
         })
+        .map(|(_, line)| line)
         .collect();
 
-    lines.join("\n")
+    (lines.join("\n"), skipped)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::cli::CodeBlocks;
 
     #[test]
     fn stripped_lines() {
@@ -200,6 +779,347 @@ mod tests {
             \n\
             And further documentation.";
 
-        assert_eq!(stripped, strip_lines(text, regexes));
+        assert_eq!(
+            (stripped.to_string(), vec![2]),
+            strip_lines(text, regexes, &[])
+        );
+    }
+
+    #[test]
+    fn decoded_encodings() {
+        // Latin-1 without a byte order mark is detected.
+        let latin1 = b"Caf\xe9 cr\xe8me, na\xefve fa\xe7ade.\n";
+        assert_eq!(
+            Some("Café crème, naïve façade.\n".to_string()),
+            decode(latin1, None).ok()
+        );
+
+        // The byte order mark sets the encoding and isn't part of the text.
+        let utf16 = b"\xff\xfeH\x00i\x00";
+        assert_eq!(Some("Hi".to_string()), decode(utf16, None).ok());
+        assert_eq!(Some("Hi".to_string()), decode(b"\xef\xbb\xbfHi", None).ok());
+
+        // Even if the encoding is configured.
+        assert_eq!(
+            Some("Hi".to_string()),
+            decode(utf16, Some(encoding_rs::UTF_8)).ok()
+        );
+
+        // UTF-16 without a byte order mark in the configured encoding.
+        assert_eq!(
+            Some("Hi".to_string()),
+            decode(b"H\x00i\x00", Some(encoding_rs::UTF_16LE)).ok()
+        );
+        assert_eq!(
+            Some("Hi".to_string()),
+            decode(b"\x00H\x00i", Some(encoding_rs::UTF_16BE)).ok()
+        );
+
+        // Binary files and invalid text are skipped.
+        assert!(matches!(
+            decode(b"\x7fELF\x02\x00", None),
+            Err(Reason::Binary)
+        ));
+        assert!(matches!(
+            decode(b"Caf\xe9", Some(encoding_rs::UTF_8)),
+            Err(Reason::NotDecodable("UTF-8"))
+        ));
+    }
+
+    #[test]
+    fn stored_size() {
+        // The size counts the stored bytes, not the decoded text.
+        let utf16 = b"\xff\xfeH\x00i\x00";
+        let file = text_file(PathBuf::from("hi.txt"), utf16, None, &Options::default()).unwrap();
+
+        assert_eq!("Hi", file.content);
+        assert_eq!(6, file.bytes);
+    }
+
+    #[test]
+    fn overlapping_roots() {
+        let dir = crate::test_dir("roots", &[("a.adoc", b"A"), ("sub/b.adoc", b"B")]);
+        let options = Options {
+            path: vec![dir.clone(), dir.join("sub")],
+            ..Options::default()
+        };
+
+        // The second root only contains files that the first root already loaded.
+        let corpus = files(&options, false).unwrap();
+        let loaded: Vec<(PathBuf, usize)> = corpus
+            .files
+            .iter()
+            .map(|file| (file.relative_path(&options).to_path_buf(), file.root))
+            .collect();
+
+        assert_eq!(
+            vec![
+                (PathBuf::from("a.adoc"), 0),
+                (PathBuf::from("sub/b.adoc"), 0)
+            ],
+            loaded
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn listed_path_separators() {
+        let dir = crate::test_dir("lists", &[("lines.txt", b"a.adoc\r\nsub/b c.adoc\n\n")]);
+        let no_stdin: &[u8] = &[];
+
+        assert_eq!(
+            vec![PathBuf::from("a.adoc"), PathBuf::from("sub/b c.adoc")],
+            listed_paths(&dir.join("lines.txt"), no_stdin).unwrap()
+        );
+
+        // The standard input, with paths that can contain new lines.
+        let stdin: &[u8] = b"a.adoc\0new\nline.adoc\0";
+        assert_eq!(
+            vec![PathBuf::from("a.adoc"), PathBuf::from("new\nline.adoc")],
+            listed_paths(Path::new("-"), stdin).unwrap()
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn listed_paths_outside_utf8() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let stdin: &[u8] = b"caf\xe9.adoc\0b.adoc\0";
+        let paths = listed_paths(Path::new("-"), stdin).unwrap();
+
+        assert_eq!(b"caf\xe9.adoc", paths[0].as_os_str().as_bytes());
+        assert_eq!(PathBuf::from("b.adoc"), paths[1]);
+    }
+
+    /// The paths of the loaded files, relative to the root directory.
+    fn loaded(options: &Options) -> Vec<String> {
+        let corpus = files(options, false).unwrap();
+
+        corpus
+            .files
+            .iter()
+            .map(|file| file.relative_path(options).display().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn walker_flags() {
+        let dir = crate::test_dir(
+            "walker",
+            &[
+                ("top.txt", b"Top"),
+                ("deep/er/down.txt", b"Down"),
+                (".hidden/secret.txt", b"Secret"),
+                (".ignore", b"ignored.txt\n"),
+                ("ignored.txt", b"Ignored"),
+                (".nfignore", b"local.txt\n"),
+                ("local.txt", b"Local"),
+            ],
+        );
+        let with = |options: Options| {
+            loaded(&Options {
+                path: vec![dir.clone()],
+                ..options
+            })
+        };
+
+        assert_eq!(
+            vec!["deep/er/down.txt", "top.txt"],
+            with(Options::default())
+        );
+        assert_eq!(
+            vec![
+                ".hidden/secret.txt",
+                ".ignore",
+                ".nfignore",
+                "deep/er/down.txt",
+                "top.txt"
+            ],
+            with(Options {
+                hidden: true,
+                ..Options::default()
+            })
+        );
+        // The `.nfignore` files apply even without the other ignore files.
+        assert_eq!(
+            vec!["deep/er/down.txt", "ignored.txt", "top.txt"],
+            with(Options {
+                no_ignore: true,
+                ..Options::default()
+            })
+        );
+        assert_eq!(
+            vec!["top.txt"],
+            with(Options {
+                max_depth: Some(1),
+                ..Options::default()
+            })
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn followed_symlinks() {
+        let outside = crate::test_dir("outside", &[("linked.txt", b"Linked")]);
+        let dir = crate::test_dir("symlinks", &[("top.txt", b"Top")]);
+        std::os::unix::fs::symlink(&outside, dir.join("link")).unwrap();
+
+        let options = Options {
+            path: vec![dir.clone()],
+            ..Options::default()
+        };
+        assert_eq!(vec!["top.txt"], loaded(&options));
+
+        let options = Options {
+            follow_symlinks: true,
+            ..options
+        };
+        assert_eq!(vec!["link/linked.txt", "top.txt"], loaded(&options));
+
+        fs::remove_dir_all(dir).unwrap();
+        fs::remove_dir_all(outside).unwrap();
+    }
+
+    #[test]
+    fn short_files() {
+        let file = File::with_content("a.txt", "One two\nthree");
+        let limits = |min_lines, min_words| Options {
+            min_lines,
+            min_words,
+            ..Options::default()
+        };
+
+        assert!(too_short(&file, &limits(None, None)).is_none());
+        assert!(too_short(&file, &limits(Some(2), Some(3))).is_none());
+        assert!(matches!(
+            too_short(&file, &limits(Some(3), None)),
+            Some(Reason::TooFewLines)
+        ));
+        assert!(matches!(
+            too_short(&file, &limits(None, Some(4))),
+            Some(Reason::TooFewWords)
+        ));
+    }
+
+    #[test]
+    fn binary_data() {
+        assert!(looks_binary(b"\x7fELF\x02\x01\x00\x00", None));
+        assert!(!looks_binary(b"Plain text.\n", None));
+        assert!(!looks_binary(b"", None));
+        // UTF-16 text contains NUL bytes, but starts with a byte order mark.
+        assert!(!looks_binary(b"\xff\xfeH\x00i\x00", None));
+        // Or the encoding is configured.
+        assert!(!looks_binary(b"H\x00i\x00", Some(encoding_rs::UTF_16LE)));
+        assert!(looks_binary(b"H\x00i\x00", Some(encoding_rs::UTF_8)));
+    }
+
+    #[test]
+    fn archived_files() {
+        use std::io::Write;
+
+        let mut tar = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(4);
+        header.set_mode(0o644);
+        header.set_cksum();
+        tar.append_data(&mut header, "guide/a.txt", &b"Tar\n"[..])
+            .unwrap();
+        let tar = tar.into_inner().unwrap();
+
+        let mut zip = zip::ZipWriter::new(io::Cursor::new(Vec::new()));
+        zip.start_file("b.txt", zip::write::SimpleFileOptions::default())
+            .unwrap();
+        zip.write_all(b"Zip\n").unwrap();
+        let zip = zip.finish().unwrap().into_inner();
+
+        let dir = crate::test_dir(
+            "archives",
+            &[
+                ("drop.tar", &tar),
+                ("drop.zip", &zip),
+                ("broken.zip", &zip[..zip.len() / 2]),
+                ("c.txt", b"Plain"),
+            ],
+        );
+        let options = Options {
+            path: vec![dir.clone()],
+            archives: true,
+            ..Options::default()
+        };
+
+        // The broken archive is skipped, and the other files still load.
+        let corpus = files(&options, false).unwrap();
+        assert_eq!(
+            vec!["c.txt", "drop.tar!/guide/a.txt", "drop.zip!/b.txt"],
+            loaded(&options)
+        );
+        assert_eq!(
+            vec!["Plain", "Tar\n", "Zip\n"],
+            corpus.files.iter().map(File::original).collect::<Vec<_>>()
+        );
+        let skipped: Vec<String> = corpus
+            .skipped
+            .iter()
+            .map(|skipped| format!("{}: {}", skipped.reason, skipped.path.display()))
+            .collect();
+        assert_eq!(
+            vec![format!(
+                "unreadable archive: {}",
+                dir.join("broken.zip").display()
+            )],
+            skipped
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn markdown_code_part() {
+        let dir = crate::test_dir(
+            "code-part",
+            &[(
+                "guide.md",
+                b"# Install\n\nRun this:\n\n```\nmake\nmake install\n```\n",
+            )],
+        );
+        let options = Options {
+            path: vec![dir.clone()],
+            format: Format::Markdown,
+            markdown_code: CodeBlocks::Separate,
+            ..Options::default()
+        };
+
+        let corpus = files(&options, false).unwrap();
+        let parts: Vec<(String, Part, &str)> = corpus
+            .files
+            .iter()
+            .map(|file| {
+                let path = file.relative_path(&options).display().to_string();
+                (path, file.part, file.content.as_str())
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                ("guide.md".to_string(), Part::Text, "Install\n\nRun this:"),
+                (
+                    "guide.md#code".to_string(),
+                    Part::CodeBlocks,
+                    "make\nmake install"
+                ),
+            ],
+            parts
+        );
+
+        // The code lines keep their line numbers in the Markdown file.
+        let code = &corpus.files[1];
+        assert_eq!(vec![5, 6], vec![code.origin(0), code.origin(1)]);
+
+        fs::remove_dir_all(dir).unwrap();
     }
 }
@@ -18,6 +18,14 @@ use color_eyre::Result;
 use simplelog::{ColorChoice, ConfigBuilder, LevelFilter, TermLogger, TerminalMode};
 
 /// Initialize the handlers for logging and error reporting.
+///
+/// # Errors
+///
+/// Fails if either of the handlers has already been installed.
+///
+/// # Panics
+///
+/// Panics if the local time zone cannot be determined.
 pub fn init_log_and_errors(verbose: u8) -> Result<()> {
     color_eyre::install()?;
 
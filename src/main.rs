@@ -16,12 +16,14 @@ limitations under the License.
 
 use color_eyre::eyre::Result;
 
-use near_facsimile::{cli, init_log_and_errors, run};
+use near_facsimile::{cli, init_log_and_errors, load_config, run};
 
 fn main() -> Result<()> {
-    let options = cli::options();
+    let mut options = cli::options();
     init_log_and_errors(options.verbose)?;
 
+    load_config(&mut options)?;
+
     run(&options)?;
 
     Ok(())
@@ -0,0 +1,146 @@
+/*
+Copyright 2022 Marek Suchánek <msuchane@redhat.com>
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Extract the prose and code from Markdown files, without the markup.
+
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use pulldown_cmark::{Event, Options, Parser, Tag, TagEnd};
+
+use crate::cli::CodeBlocks;
+
+/// The suffix of the path of the code blocks, when they're compared separately.
+const CODE_SUFFIX: &str = "#code";
+
+/// The text content of a Markdown file.
+pub struct Extracted {
+    pub prose: String,
+    /// The code blocks, if they're compared separately.
+    pub code: Option<String>,
+}
+
+/// Extract the prose from the Markdown text, and handle the code blocks as configured.
+/// The link URLs, reference definitions, HTML, front matter, and formatting punctuation
+/// aren't part of the text.
+pub fn extract(text: &str, code_blocks: CodeBlocks) -> Extracted {
+    let options = Options::ENABLE_TABLES
+        | Options::ENABLE_FOOTNOTES
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_TASKLISTS
+        | Options::ENABLE_YAML_STYLE_METADATA_BLOCKS
+        | Options::ENABLE_PLUSES_DELIMITED_METADATA_BLOCKS;
+
+    let mut prose = String::new();
+    let mut code = String::new();
+    let mut in_code_block = false;
+    let mut in_metadata = false;
+
+    for event in Parser::new_ext(text, options) {
+        match event {
+            Event::Start(Tag::CodeBlock(_)) => in_code_block = true,
+            Event::End(TagEnd::CodeBlock) => {
+                in_code_block = false;
+                end_block(&mut code);
+                end_block(&mut prose);
+            }
+            Event::Start(Tag::MetadataBlock(_)) => in_metadata = true,
+            Event::End(TagEnd::MetadataBlock(_)) => in_metadata = false,
+            Event::Text(text) if in_code_block => match code_blocks {
+                CodeBlocks::Include => prose.push_str(&text),
+                CodeBlocks::Ignore => {}
+                CodeBlocks::Separate => code.push_str(&text),
+            },
+            Event::Text(text) | Event::Code(text) if !in_metadata => prose.push_str(&text),
+            Event::SoftBreak | Event::HardBreak => prose.push('\n'),
+            Event::End(TagEnd::TableCell) => prose.push(' '),
+            Event::End(
+                TagEnd::Paragraph
+                | TagEnd::Heading(_)
+                | TagEnd::Item
+                | TagEnd::TableHead
+                | TagEnd::TableRow
+                | TagEnd::FootnoteDefinition,
+            ) => end_block(&mut prose),
+            // The HTML, including comments, and the rest of the markup.
+            _ => {}
+        }
+    }
+
+    Extracted {
+        prose: prose.trim_end().to_string(),
+        code: (code_blocks == CodeBlocks::Separate && !code.trim().is_empty())
+            .then(|| code.trim_end().to_string()),
+    }
+}
+
+/// Separate the finished block from the next one by a blank line.
+fn end_block(text: &mut String) {
+    if !text.is_empty() && !text.ends_with("\n\n") {
+        if !text.ends_with('\n') {
+            text.push('\n');
+        }
+        text.push('\n');
+    }
+}
+
+/// The virtual path of the code blocks of a file, such as `guide.md#code`.
+pub fn code_path(path: &Path) -> PathBuf {
+    let mut code = OsString::from(path);
+    code.push(CODE_SUFFIX);
+    PathBuf::from(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extracted_prose() {
+        let text = "---\n\
+            title: Guide\n\
+            ---\n\
+            # Getting **started**\n\
+            \n\
+            Read the [manual](https://example.com/manual) and the [FAQ][faq].\n\
+            <!-- A comment. -->\n\
+            \n\
+            ```sh\n\
+            make install\n\
+            ```\n\
+            \n\
+            [faq]: https://example.com/faq\n";
+
+        let included = extract(text, CodeBlocks::Include);
+        assert_eq!(
+            "Getting started\n\nRead the manual and the FAQ.\n\nmake install",
+            included.prose
+        );
+        assert_eq!(None, included.code);
+
+        let separate = extract(text, CodeBlocks::Separate);
+        assert_eq!(
+            "Getting started\n\nRead the manual and the FAQ.",
+            separate.prose
+        );
+        assert_eq!(Some("make install".to_string()), separate.code);
+
+        assert_eq!(
+            PathBuf::from("docs/guide.md#code"),
+            code_path(Path::new("docs/guide.md"))
+        );
+    }
+}
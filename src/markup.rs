@@ -0,0 +1,290 @@
+/*
+Copyright 2022 Marek Suchánek <msuchane@redhat.com>
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Extract the text from HTML and XML files, such as DocBook, without the markup.
+//!
+//! The scanner is lenient rather than validating, because generated HTML
+//! is rarely well-formed XML.
+
+/// The elements that start a new line with `--markup-structure`, in HTML and DocBook.
+const BLOCK_ELEMENTS: &[&str] = &[
+    // HTML
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "br",
+    "dd",
+    "div",
+    "dl",
+    "dt",
+    "figcaption",
+    "figure",
+    "footer",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hr",
+    "li",
+    "nav",
+    "ol",
+    "p",
+    "pre",
+    "section",
+    "table",
+    "td",
+    "th",
+    "title",
+    "tr",
+    "ul",
+    // DocBook
+    "abstract",
+    "appendix",
+    "caution",
+    "chapter",
+    "entry",
+    "example",
+    "formalpara",
+    "important",
+    "itemizedlist",
+    "listitem",
+    "note",
+    "orderedlist",
+    "para",
+    "preface",
+    "procedure",
+    "programlisting",
+    "row",
+    "screen",
+    "sect1",
+    "sect2",
+    "sect3",
+    "sect4",
+    "sect5",
+    "simpara",
+    "step",
+    "term",
+    "tip",
+    "varlistentry",
+    "variablelist",
+    "warning",
+];
+
+/// The HTML elements whose content isn't text.
+const HIDDEN_ELEMENTS: &[&str] = &["script", "style"];
+
+/// Extract the text nodes from the markup. The tags, attributes, comments,
+/// and declarations aren't part of the text. With `structure`, each block element
+/// starts a new line, and otherwise it only separates its words from the words around it.
+/// In HTML, the scripts and styles aren't part of the text either.
+pub fn extract(text: &str, html: bool, structure: bool) -> String {
+    let mut output = String::new();
+    let mut rest = text;
+
+    while let Some(start) = rest.find('<') {
+        output.push_str(&decode_entities(&rest[..start]));
+        rest = &rest[start..];
+
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = after(comment, "-->");
+        } else if let Some(cdata) = rest.strip_prefix("<![CDATA[") {
+            let end = cdata.find("]]>").unwrap_or(cdata.len());
+            output.push_str(&cdata[..end]);
+            rest = after(cdata, "]]>");
+        } else if let Some(instruction) = rest.strip_prefix("<?") {
+            rest = after(instruction, "?>");
+        } else if let Some(declaration) = rest.strip_prefix("<!") {
+            rest = skip_declaration(declaration);
+        } else if let Some((name, closing, tag_end)) = tag(rest) {
+            rest = &rest[tag_end..];
+
+            if html && !closing && HIDDEN_ELEMENTS.contains(&name.as_str()) {
+                rest = skip_element(rest, &name);
+            } else if BLOCK_ELEMENTS.contains(&name.as_str()) {
+                if structure {
+                    output.push('\n');
+                } else if !output.ends_with(char::is_whitespace) {
+                    output.push(' ');
+                }
+            }
+        } else {
+            // A `<` that doesn't start a tag, such as in `a < b` in HTML.
+            output.push('<');
+            rest = &rest[1..];
+        }
+    }
+    output.push_str(&decode_entities(rest));
+
+    // The indentation of the markup isn't part of the text.
+    output
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// The rest of the text after the delimiter, or nothing if the delimiter is missing.
+fn after<'a>(text: &'a str, delimiter: &str) -> &'a str {
+    text.find(delimiter)
+        .map_or("", |end| &text[end + delimiter.len()..])
+}
+
+/// Skip a declaration, such as `<!DOCTYPE>`, including its internal subset of entities.
+fn skip_declaration(declaration: &str) -> &str {
+    let end = declaration.find('>').unwrap_or(declaration.len());
+
+    match declaration[..end].find('[') {
+        Some(_) => after(declaration, "]>"),
+        None => after(declaration, ">"),
+    }
+}
+
+/// Parse the tag at the start of the text, as the local name of the element
+/// in lowercase, whether it's a closing tag, and the length of the tag.
+/// Returns `None` if the text doesn't start with a tag.
+fn tag(text: &str) -> Option<(String, bool, usize)> {
+    let inner = text.strip_prefix('<')?;
+    let (closing, inner) = match inner.strip_prefix('/') {
+        Some(inner) => (true, inner),
+        None => (false, inner),
+    };
+
+    if !inner.starts_with(|character: char| character.is_ascii_alphabetic()) {
+        return None;
+    }
+
+    // Find the end of the tag, outside of the quoted attribute values.
+    let mut quote = None;
+    let end = inner.char_indices().find_map(|(index, character)| {
+        match (quote, character) {
+            (None, '"' | '\'') => quote = Some(character),
+            (Some(open), _) if open == character => quote = None,
+            (None, '>') => return Some(index),
+            _ => {}
+        }
+        None
+    })?;
+
+    let name = inner[..end]
+        .split(|character: char| character.is_whitespace() || character == '/')
+        .next()
+        .unwrap_or_default();
+    // Drop the namespace prefix, such as in `<db:para>`.
+    let local = name.rsplit(':').next().unwrap_or(name).to_lowercase();
+    let length = text.len() - inner.len() + end + 1;
+
+    Some((local, closing, length))
+}
+
+/// Skip the content of the element, up to and including its closing tag.
+fn skip_element<'a>(text: &'a str, name: &str) -> &'a str {
+    // The tag names are ASCII, so the search doesn't need to lowercase the whole text.
+    let closing = text.match_indices("</").find(|(start, _)| {
+        text[start + 2..]
+            .get(..name.len())
+            .is_some_and(|tag| tag.eq_ignore_ascii_case(name))
+    });
+
+    match closing {
+        Some((start, _)) => after(&text[start..], ">"),
+        None => "",
+    }
+}
+
+/// Replace the character references and the common named entities with their characters.
+/// Unknown entities, such as those that a DocBook document defines, stay as they are.
+fn decode_entities(text: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('&') {
+        output.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let decoded = rest.find(';').filter(|&end| end <= 10).and_then(|end| {
+            let entity = &rest[1..end];
+            let character = match entity {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                "nbsp" => Some(' '),
+                _ => entity
+                    .strip_prefix("#x")
+                    .or_else(|| entity.strip_prefix("#X"))
+                    .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                    .or_else(|| entity.strip_prefix('#').and_then(|dec| dec.parse().ok()))
+                    .and_then(char::from_u32),
+            };
+            character.map(|character| (character, end))
+        });
+
+        if let Some((character, end)) = decoded {
+            output.push(character);
+            rest = &rest[end + 1..];
+        } else {
+            output.push('&');
+            rest = &rest[1..];
+        }
+    }
+    output.push_str(rest);
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extracted_text() {
+        let docbook = r#"<?xml version="1.0"?>
+<!DOCTYPE book [ <!ENTITY product "Example"> ]>
+<db:section xmlns:db="http://docbook.org/ns/docbook" xml:id="intro">
+  <db:title>Installing &product;</db:title>
+  <!-- A comment. -->
+  <db:para>Run the <db:command>install</db:command> command &amp; wait.</db:para>
+  <db:programlisting><![CDATA[make <all>]]></db:programlisting>
+</db:section>"#;
+
+        assert_eq!(
+            "Installing &product;\nRun the install command & wait.\nmake <all>",
+            extract(docbook, false, true)
+        );
+
+        let html = "<html><head><style>p { color: red; }</style>\
+            <script>if (a < b) {}</script></head>\
+            <body><p class='x'>One&nbsp;&#x32;</p><p>Three</p></body></html>";
+
+        assert_eq!("One 2 Three", extract(html, true, false));
+        assert_eq!("One 2\nThree", extract(html, true, true));
+    }
+
+    #[test]
+    fn skipped_elements_with_other_scripts() {
+        // The Kelvin sign is shorter in lowercase, so the offsets in lowercase text would differ.
+        let html = "<script>let k = '\u{212a}\u{212a}';</SCRIPT><p>Ångström, \u{212a}</p>\
+            <style>p::after { content: 'İ'; }</Style>";
+
+        assert_eq!("Ångström, \u{212a}", extract(html, true, false));
+    }
+}
@@ -0,0 +1,113 @@
+/*
+Copyright 2022 Marek Suchánek <msuchane@redhat.com>
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Normalize the compared content, so that formatting changes don't affect the similarity.
+
+use unicode_normalization::UnicodeNormalization;
+
+use crate::cli::Normalization;
+
+/// Apply the selected normalizations to the text. They always apply in the same order,
+/// regardless of the order of the options, so that they don't interfere with each other.
+pub fn normalize(text: &str, normalizations: &[Normalization]) -> String {
+    let enabled = |normalization| normalizations.contains(&normalization);
+    let mut text = text.to_string();
+
+    // The compatibility decomposition also turns some punctuation into plain characters,
+    // so it comes first.
+    if enabled(Normalization::Nfkc) {
+        text = text.nfkc().collect();
+    }
+    if enabled(Normalization::Punctuation) {
+        text = text.chars().map(plain_punctuation).collect();
+    }
+    if enabled(Normalization::Case) {
+        text = text.to_lowercase();
+    }
+    if enabled(Normalization::Unwrap) {
+        text = unwrap_paragraphs(&text);
+    }
+    if enabled(Normalization::Whitespace) {
+        text = collapse_whitespace(&text);
+    }
+
+    text
+}
+
+/// Replace typographic quotes and dashes with their plain ASCII counterparts.
+fn plain_punctuation(character: char) -> char {
+    match character {
+        '‘' | '’' | '‚' | '‛' | '′' => '\'',
+        '“' | '”' | '„' | '‟' | '″' | '«' | '»' => '"',
+        '‐' | '‑' | '‒' | '–' | '—' | '―' | '−' => '-',
+        other => other,
+    }
+}
+
+/// Join the lines of each paragraph into a single line. Paragraphs are separated
+/// by blank lines.
+fn unwrap_paragraphs(text: &str) -> String {
+    text.split('\n')
+        .map(str::trim)
+        .collect::<Vec<_>>()
+        .split(|line| line.is_empty())
+        .filter(|paragraph| !paragraph.is_empty())
+        .map(|paragraph| paragraph.join(" "))
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+/// Replace each sequence of spaces and tabs with a single space, trim the lines,
+/// and merge consecutive blank lines.
+fn collapse_whitespace(text: &str) -> String {
+    let mut lines: Vec<String> = Vec::new();
+
+    for line in text.lines() {
+        let line = line.split_whitespace().collect::<Vec<_>>().join(" ");
+        let previous_blank = lines.last().is_some_and(String::is_empty);
+
+        if !(line.is_empty() && previous_blank) {
+            lines.push(line);
+        }
+    }
+
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalized_formatting() {
+        let original = "The “Quick”  Start—guide\nexplains   it.\n\n\n\nＮext paragraph.";
+        let rewrapped = "the \"quick\" start-guide explains it.\n\nnext paragraph.";
+
+        let all = [
+            Normalization::Whitespace,
+            Normalization::Unwrap,
+            Normalization::Case,
+            Normalization::Nfkc,
+            Normalization::Punctuation,
+        ];
+
+        assert_eq!(rewrapped, normalize(original, &all));
+        assert_eq!(
+            "The “Quick” Start—guide\nexplains it.\n\nＮext paragraph.",
+            normalize(original, &[Normalization::Whitespace])
+        );
+    }
+}
@@ -0,0 +1,241 @@
+/*
+Copyright 2022 Marek Suchánek <msuchane@redhat.com>
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! The settings that control how the library loads and compares files,
+//! independently of the command-line interface.
+
+use std::ffi::OsString;
+use std::path::PathBuf;
+
+use color_eyre::eyre::bail;
+use color_eyre::Result;
+use regex::Regex;
+
+use crate::cli::{Block, CodeBlocks, Format, Metric, Normalization, Replace, Rule, Tokens};
+use crate::Cli;
+
+/// The settings for loading and comparing files. Each field corresponds to the command-line
+/// option of the same name, as described in the `--help` output.
+///
+/// Start from the defaults and change the fields that you need:
+///
+/// ```
+/// use near_facsimile::Options;
+///
+/// let options = Options {
+///     path: vec!["docs".into()],
+///     threshold: 0.9,
+///     ..Options::default()
+/// };
+/// ```
+// The options are naturally booleans.
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Clone, Debug)]
+pub struct Options {
+    /// The root directories of the compared files.
+    pub path: Vec<PathBuf>,
+    /// The similarity threshold as a decimal value between 0.0 and 1.0,
+    /// rather than a percentage.
+    pub threshold: f64,
+    pub metric: Metric,
+    /// Calculate the file sizes, scores, and line changes of the similar pairs.
+    pub details: bool,
+    pub ignore_file: Vec<OsString>,
+    pub ignore_ext: Vec<OsString>,
+    pub require_file: Vec<OsString>,
+    pub require_ext: Vec<OsString>,
+    /// Load the paths listed in this file, or in the standard input if it's `-`,
+    /// instead of searching the root directories.
+    pub files_from: Option<PathBuf>,
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    pub hidden: bool,
+    pub no_ignore: bool,
+    pub follow_symlinks: bool,
+    pub max_depth: Option<usize>,
+    pub archives: bool,
+    /// The encoding label of all files, or `auto` to detect it in each file.
+    pub encoding: String,
+    /// The size limits in bytes.
+    pub max_file_size: Option<u64>,
+    pub min_file_size: Option<u64>,
+    pub min_lines: Option<usize>,
+    pub min_words: Option<usize>,
+    pub skip_lines: Vec<Regex>,
+    pub format: Format,
+    pub asciidoc_includes: bool,
+    pub asciidoc_attributes: Option<PathBuf>,
+    pub asciidoc_ignore_anchors: bool,
+    pub asciidoc_ignore_conditionals: bool,
+    pub markdown_code: CodeBlocks,
+    pub markup_structure: bool,
+    pub code_rename_identifiers: bool,
+    pub code_rename_literals: bool,
+    pub tokens: Tokens,
+    pub stopwords: Option<PathBuf>,
+    /// The percentage of files, between 0.0 and 100.0, in which content counts as boilerplate.
+    pub boilerplate: Option<f64>,
+    pub strip_boilerplate: bool,
+    pub skip_block: Vec<Block>,
+    pub replace: Vec<Replace>,
+    pub normalize: Vec<Normalization>,
+    pub rule: Vec<Rule>,
+}
+
+impl Default for Options {
+    /// The same defaults as on the command line.
+    fn default() -> Self {
+        Self {
+            path: vec![PathBuf::from(".")],
+            threshold: 0.85,
+            metric: Metric::Levenshtein,
+            details: false,
+            ignore_file: Vec::new(),
+            ignore_ext: Vec::new(),
+            require_file: Vec::new(),
+            require_ext: Vec::new(),
+            files_from: None,
+            include: Vec::new(),
+            exclude: Vec::new(),
+            hidden: false,
+            no_ignore: false,
+            follow_symlinks: false,
+            max_depth: None,
+            archives: false,
+            encoding: "auto".to_string(),
+            max_file_size: None,
+            min_file_size: None,
+            min_lines: None,
+            min_words: None,
+            skip_lines: Vec::new(),
+            format: Format::Plain,
+            asciidoc_includes: false,
+            asciidoc_attributes: None,
+            asciidoc_ignore_anchors: false,
+            asciidoc_ignore_conditionals: false,
+            markdown_code: CodeBlocks::Include,
+            markup_structure: false,
+            code_rename_identifiers: false,
+            code_rename_literals: false,
+            tokens: Tokens::Characters,
+            stopwords: None,
+            boilerplate: None,
+            strip_boilerplate: false,
+            skip_block: Vec::new(),
+            replace: Vec::new(),
+            normalize: Vec::new(),
+            rule: Vec::new(),
+        }
+    }
+}
+
+impl From<&Cli> for Options {
+    /// Take the loading and comparison settings from the command line. The output
+    /// and logging options stay with the command line.
+    fn from(cli: &Cli) -> Self {
+        Self {
+            path: cli.path.clone(),
+            threshold: cli.threshold,
+            metric: cli.metric(),
+            details: cli.details,
+            ignore_file: cli.ignore_file.clone(),
+            ignore_ext: cli.ignore_ext.clone(),
+            require_file: cli.require_file.clone(),
+            require_ext: cli.require_ext.clone(),
+            files_from: cli.files_from.clone(),
+            include: cli.include.clone(),
+            exclude: cli.exclude.clone(),
+            hidden: cli.hidden,
+            no_ignore: cli.no_ignore,
+            follow_symlinks: cli.follow_symlinks,
+            max_depth: cli.max_depth,
+            archives: cli.archives,
+            encoding: cli.encoding.clone(),
+            max_file_size: cli.max_file_size,
+            min_file_size: cli.min_file_size,
+            min_lines: cli.min_lines,
+            min_words: cli.min_words,
+            skip_lines: cli.skip_lines.clone(),
+            format: cli.format,
+            asciidoc_includes: cli.asciidoc_includes,
+            asciidoc_attributes: cli.asciidoc_attributes.clone(),
+            asciidoc_ignore_anchors: cli.asciidoc_ignore_anchors,
+            asciidoc_ignore_conditionals: cli.asciidoc_ignore_conditionals,
+            markdown_code: cli.markdown_code,
+            markup_structure: cli.markup_structure,
+            code_rename_identifiers: cli.code_rename_identifiers,
+            code_rename_literals: cli.code_rename_literals,
+            tokens: cli.tokens,
+            stopwords: cli.stopwords.clone(),
+            boilerplate: cli.boilerplate,
+            strip_boilerplate: cli.strip_boilerplate,
+            skip_block: cli.skip_block.clone(),
+            replace: cli.replace.clone(),
+            normalize: cli.normalize.clone(),
+            rule: cli.rule.clone(),
+        }
+    }
+}
+
+impl Options {
+    /// Check that the percentages are within their ranges.
+    pub(crate) fn validate(&self) -> Result<()> {
+        // Each file belongs to a root directory, even if it comes from a list.
+        if self.path.is_empty() {
+            bail!("The path option needs at least one root directory.")
+        }
+        if !(0.0..=1.0).contains(&self.threshold) {
+            bail!("The similarity threshold must be between 0.0 and 100.0.")
+        }
+        if let Some(percentage) = self.boilerplate {
+            if !(0.0..=100.0).contains(&percentage) {
+                bail!("The boilerplate percentage must be between 0.0 and 100.0.")
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+
+    #[test]
+    fn defaults_match_the_command_line() {
+        let mut cli = Cli::parse_from(["near-facsimile"]);
+        cli.threshold /= 100.0;
+
+        assert_eq!(
+            format!("{:?}", Options::default()),
+            format!("{:?}", Options::from(&cli))
+        );
+    }
+
+    #[test]
+    fn no_root_directory() {
+        let options = Options {
+            path: Vec::new(),
+            files_from: Some("-".into()),
+            ..Options::default()
+        };
+        assert!(options.validate().is_err());
+        assert!(crate::find_similar(&options).is_err());
+        assert!(Options::default().validate().is_ok());
+    }
+}
@@ -54,6 +54,8 @@ mod tests {
     use super::*;
 
     #[test]
+    // The rounded values are exact, so comparing them strictly is intended.
+    #[allow(clippy::float_cmp)]
     fn check_percentage() {
         assert_eq!(90.0, Percentage::from(0.9).rounded());
         assert_eq!(99.9, Percentage::from(0.999).rounded());
@@ -0,0 +1,168 @@
+/*
+Copyright 2022 Marek Suchánek <msuchane@redhat.com>
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! The outcome of a run, independent of how the terminal or the output files present it.
+
+use std::time::Duration;
+
+use color_eyre::Result;
+
+use crate::boilerplate::Boilerplate;
+use crate::clusters::clusters;
+use crate::results::Results;
+use crate::serialize::build_results;
+use crate::{Comparison, Corpus, Options};
+
+/// Everything that a run found.
+#[derive(Debug)]
+pub struct Report {
+    /// The similar pairs, from the most similar, and the skipped files,
+    /// in the same structure as the JSON output.
+    pub results: Results,
+    /// The groups of files that are similar to each other, as labeled paths.
+    /// The largest clusters come first.
+    pub clusters: Vec<Vec<String>>,
+    /// The lines and paragraphs that many files share, with `--boilerplate`.
+    pub boilerplate: Vec<Boilerplate>,
+    pub timing: Timing,
+}
+
+/// How long each stage of the run took.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Timing {
+    /// Searching, reading, and processing each file.
+    pub loading: Duration,
+    /// Processing the content that depends on all files, such as boilerplate and weights.
+    pub preparing: Duration,
+    /// Comparing the pairs of files.
+    pub comparing: Duration,
+}
+
+impl Timing {
+    #[must_use]
+    pub fn total(&self) -> Duration {
+        self.loading + self.preparing + self.comparing
+    }
+}
+
+impl Report {
+    /// Convert the comparisons of the loaded files to the owned report.
+    pub(crate) fn new(
+        comparisons: &[Comparison],
+        corpus: &Corpus,
+        options: &Options,
+        timing: Timing,
+    ) -> Result<Self> {
+        let results = build_results(comparisons, corpus.files.len(), &corpus.skipped, options)?;
+
+        let pairs = results
+            .comparisons
+            .iter()
+            .map(|comparison| (comparison.labeled_file1(), comparison.labeled_file2()));
+        let clusters = clusters(pairs);
+
+        Ok(Self {
+            results,
+            clusters,
+            boilerplate: corpus.boilerplate.clone(),
+            timing,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::cli::Metric;
+    use crate::percentage::Percentage;
+    use crate::File;
+
+    fn comparison<'a>(file1: &'a File, file2: &'a File, score: f64) -> Comparison<'a> {
+        Comparison {
+            file1,
+            file2,
+            similarity_pct: Percentage::from(score),
+            metric: Metric::Levenshtein,
+            score,
+            trigram: score,
+            details: None,
+        }
+    }
+
+    #[test]
+    // The rounded percentages are exact, so comparing them strictly is intended.
+    #[allow(clippy::float_cmp)]
+    fn pairs_and_clusters() {
+        let corpus = Corpus {
+            files: ["a", "b", "c", "d"]
+                .map(|path| File::with_content(path, ""))
+                .into(),
+            skipped: Vec::new(),
+            boilerplate: Vec::new(),
+            listing: Vec::new(),
+        };
+        let files = &corpus.files;
+        let options = Options {
+            path: vec![PathBuf::new()],
+            ..Options::default()
+        };
+        let comparisons = [
+            comparison(&files[0], &files[1], 1.0),
+            comparison(&files[1], &files[2], 0.9),
+        ];
+
+        let report = Report::new(&comparisons, &corpus, &options, Timing::default()).unwrap();
+
+        assert_eq!(2, report.results.comparisons.len());
+        assert_eq!(100.0, report.results.comparisons[0].pct_similar);
+        assert_eq!(
+            Some(4),
+            report.results.metadata.map(|metadata| metadata.file_count)
+        );
+        assert_eq!(vec![vec!["a", "b", "c"]], report.clusters);
+    }
+
+    #[test]
+    fn boilerplate_in_the_report() {
+        let header: &[u8] = b"Copyright Example\n\n";
+        let dir = crate::test_dir(
+            "report-boilerplate",
+            &[
+                ("a.txt", &[header, b"First topic."].concat()),
+                ("b.txt", &[header, b"Second topic."].concat()),
+                ("c.txt", b"Third topic."),
+            ],
+        );
+        let options = Options {
+            path: vec![dir.clone()],
+            boilerplate: Some(50.0),
+            ..Options::default()
+        };
+
+        let report = crate::find_similar(&options).unwrap();
+        assert_eq!(
+            vec![Boilerplate {
+                lines: vec!["Copyright Example".to_string()],
+                files: 2,
+            }],
+            report.boilerplate
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,118 @@
+/*
+Copyright 2022 Marek Suchánek <msuchane@redhat.com>
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Print the report to the terminal through the log.
+
+use std::collections::BTreeMap;
+
+use owo_colors::{OwoColorize, Stream};
+
+use crate::{Boilerplate, Cli, Corpus, Options, Report};
+
+/// With `--list-files`, print the decision about each file. Print how many files
+/// were skipped for each reason, and with `--list-skipped`, which files.
+pub fn log_selection(corpus: &Corpus, cli: &Cli) {
+    if cli.list_files {
+        for decision in &corpus.listing {
+            log::info!("{decision}");
+        }
+    // The dry run already lists each file.
+    } else if cli.list_skipped {
+        for skipped in &corpus.skipped {
+            log::info!("Skipped ({}): {}", skipped.reason, skipped.path.display());
+        }
+    }
+
+    let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+    for skipped in &corpus.skipped {
+        *counts.entry(skipped.reason.to_string()).or_default() += 1;
+    }
+
+    let total = corpus.skipped.len();
+
+    if total > 0 {
+        let reasons = counts
+            .iter()
+            .map(|(reason, count)| format!("  ‣ {count}: {reason}"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        log::info!("Number of skipped files: {total}\n{reasons}");
+    }
+}
+
+/// Print out the boilerplate, each similar pair, from the most similar, and how long the run took.
+pub fn log_report(report: &Report, options: &Options) {
+    if let Some(percentage) = options.boilerplate {
+        log_boilerplate(&report.boilerplate, percentage);
+    }
+
+    for comparison in &report.results.comparisons {
+        // Prepare the listing of the file pair before printing.
+        let file_display = format!(
+            "  ‣ {}\n  ‣ {}",
+            comparison.labeled_file1(),
+            comparison.labeled_file2(),
+        );
+        let percent = comparison.pct_similar;
+
+        // The rounded percentage only reaches 100.0 for identical files.
+        if percent >= 100.0 {
+            let message = format!("These two files are identical ({percent:.1}%):");
+            log::info!(
+                "{}\n{}",
+                message.if_supports_color(Stream::Stdout, OwoColorize::red),
+                file_display,
+            );
+        } else {
+            let message = format!("These two files are similar ({percent:.1}%):");
+            log::info!(
+                "{}\n{}",
+                message.if_supports_color(Stream::Stdout, OwoColorize::yellow),
+                file_display
+            );
+        }
+    }
+
+    let timing = &report.timing;
+    log::debug!(
+        "Finished in {:.2?}: loading {:.2?}, preparing {:.2?}, comparing {:.2?}",
+        timing.total(),
+        timing.loading,
+        timing.preparing,
+        timing.comparing
+    );
+}
+
+/// Print the boilerplate, showing the first line of each paragraph.
+fn log_boilerplate(found: &[Boilerplate], percentage: f64) {
+    if found.is_empty() {
+        log::info!("No boilerplate appears in more than {percentage}% of files.");
+        return;
+    }
+
+    let items: Vec<String> = found
+        .iter()
+        .map(|item| {
+            let more = if item.lines.len() > 1 { " …" } else { "" };
+            format!("  ‣ {} files: {}{more}", item.files, item.lines[0])
+        })
+        .collect();
+
+    log::info!(
+        "Boilerplate in more than {percentage}% of files:\n{}",
+        items.join("\n")
+    );
+}
@@ -0,0 +1,240 @@
+/*
+Copyright 2022 Marek Suchánek <msuchane@redhat.com>
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! The format of the JSON results file, and a reader that loads results files
+//! of any supported version.
+
+use std::fs;
+use std::path::Path;
+
+use color_eyre::eyre::{bail, WrapErr};
+use color_eyre::Result;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// The version of the JSON results format that this release writes.
+///
+/// Increase the version whenever the structure of the results changes
+/// in a way that older readers can't handle.
+///
+/// Version 0 was a bare array of comparisons, without any metadata.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// The complete JSON results: information about the run, followed by the comparisons.
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct Results {
+    /// The version of this results format.
+    pub schema_version: u32,
+    /// Information about the run. Results of version 0 don't include it.
+    pub metadata: Option<Metadata>,
+    /// The similar pairs, from the most similar to the least similar.
+    pub comparisons: Vec<OutputComparison>,
+    /// The files that didn't take part in the comparison, if the run listed them.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub skipped: Vec<SkippedFile>,
+}
+
+/// Information about the run that produced the results.
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct Metadata {
+    /// The version of near-facsimile.
+    pub version: String,
+    /// The time of the run in the RFC 3339 format.
+    pub timestamp: String,
+    /// The similarity percentage above which files were reported.
+    pub threshold: f64,
+    /// The name of the comparison metric.
+    pub metric: String,
+    /// The units that the metric compared, unless they were characters.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tokens: Option<String>,
+    pub filters: Filters,
+    /// The compared root directories.
+    #[serde(default)]
+    pub roots: Vec<String>,
+    /// The number of loaded files that entered the comparison.
+    pub file_count: usize,
+}
+
+/// The options that selected the compared files and their content.
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct Filters {
+    pub ignore_file: Vec<String>,
+    pub ignore_ext: Vec<String>,
+    pub require_file: Vec<String>,
+    pub require_ext: Vec<String>,
+    #[serde(default)]
+    pub include: Vec<String>,
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    pub skip_lines: Vec<String>,
+    #[serde(default)]
+    pub skip_block: Vec<String>,
+    #[serde(default)]
+    pub replace: Vec<String>,
+    #[serde(default)]
+    pub normalize: Vec<String>,
+}
+
+/// A record of a file comparison, formatted to be serialized for the user.
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct OutputComparison {
+    pub pct_similar: f64,
+    /// The path of the first file, relative to its root directory.
+    pub file1: String,
+    /// The path of the second file, relative to its root directory.
+    pub file2: String,
+    /// The root directory of the first file, if the comparison spans several of them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub root1: Option<String>,
+    /// The root directory of the second file, if the comparison spans several of them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub root2: Option<String>,
+    #[serde(flatten)]
+    pub details: Option<OutputDetails>,
+}
+
+impl OutputComparison {
+    /// The path of the first file, prefixed with the label of its root directory, if any.
+    #[must_use]
+    pub fn labeled_file1(&self) -> String {
+        labeled(self.root1.as_deref(), &self.file1)
+    }
+
+    /// The path of the second file, prefixed with the label of its root directory, if any.
+    #[must_use]
+    pub fn labeled_file2(&self) -> String {
+        labeled(self.root2.as_deref(), &self.file2)
+    }
+}
+
+/// Prefix the path with the label of its root directory, if any, as `<ROOT>:<PATH>`.
+pub(crate) fn labeled(root: Option<&str>, path: &str) -> String {
+    match root {
+        Some(root) => format!("{root}:{path}"),
+        None => path.to_string(),
+    }
+}
+
+/// A file that didn't take part in the comparison, and why.
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct SkippedFile {
+    /// The path of the file, relative to its root directory.
+    pub file: String,
+    /// The root directory of the file, if the run spans several of them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub root: Option<String>,
+    pub reason: String,
+}
+
+/// The optional, detailed statistics about a file comparison.
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct OutputDetails {
+    pub bytes1: usize,
+    pub bytes2: usize,
+    pub lines1: usize,
+    pub lines2: usize,
+    pub metric: String,
+    pub score: f64,
+    pub trigram: f64,
+    /// The edit distance, unless the metric compared the files in a different way.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub edit_distance: Option<usize>,
+    pub lines_added: usize,
+    pub lines_removed: usize,
+    pub identical: bool,
+}
+
+/// Load a JSON results file of any supported version, converted to the current version.
+///
+/// # Errors
+///
+/// Fails if the file can't be read, if it isn't valid JSON, if it doesn't match
+/// the format of its version, or if the version is newer than this release supports.
+pub fn read_json(file: &Path) -> Result<Results> {
+    let text = fs::read_to_string(file)
+        .wrap_err_with(|| format!("Failed to read the results file: {}", file.display()))?;
+
+    parse_json(&text).wrap_err_with(|| format!("Invalid results file: {}", file.display()))
+}
+
+/// Parse JSON results of any supported version, converted to the current version.
+///
+/// # Errors
+///
+/// Fails if the text isn't valid JSON, if it doesn't match the format of its version,
+/// or if the version is newer than this release supports.
+pub fn parse_json(text: &str) -> Result<Results> {
+    let value: Value = serde_json::from_str(text)?;
+
+    // Version 0 is the only one that isn't an object.
+    if value.is_array() {
+        return Ok(Results {
+            schema_version: SCHEMA_VERSION,
+            metadata: None,
+            comparisons: serde_json::from_value(value)?,
+            skipped: Vec::new(),
+        });
+    }
+
+    match value.get("schema_version").and_then(Value::as_u64) {
+        Some(1) => Ok(serde_json::from_value(value)?),
+        Some(version) => bail!(
+            "Unsupported results version {version}. This release supports versions up to {SCHEMA_VERSION}."
+        ),
+        None => bail!("The results are missing the `schema_version` entry."),
+    }
+}
+
+/// Generate the JSON Schema that describes the current version of the results.
+///
+/// # Panics
+///
+/// Doesn't panic in practice, because the generated schema is always plain JSON data.
+#[must_use]
+pub fn json_schema() -> String {
+    let schema = schemars::schema_for!(Results);
+
+    serde_json::to_string_pretty(&schema).expect("Failed to serialize the JSON Schema.") + "\n"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The published schema in the repository must match the current types.
+    /// To update it, run the tests with the `UPDATE_SCHEMA` environment variable set.
+    #[test]
+    fn published_schema() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("schema/results.schema.json");
+
+        if std::env::var_os("UPDATE_SCHEMA").is_some() {
+            fs::write(&path, json_schema()).unwrap();
+        }
+
+        assert_eq!(fs::read_to_string(path).unwrap(), json_schema());
+    }
+
+    #[test]
+    fn legacy_array() {
+        let results = parse_json(r#"[{"pct_similar": 92.5, "file1": "a", "file2": "b"}]"#).unwrap();
+
+        assert!(results.metadata.is_none());
+        assert_eq!("b", results.comparisons[0].file2);
+        assert!(results.comparisons[0].details.is_none());
+    }
+}
@@ -0,0 +1,82 @@
+/*
+Copyright 2022 Marek Suchánek <msuchane@redhat.com>
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::path::Path;
+
+use color_eyre::eyre::WrapErr;
+use color_eyre::Result;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+
+use crate::cli::Rule;
+use crate::{File, Options};
+
+/// The rules from the options, with their glob patterns compiled for matching.
+pub struct Rules<'a> {
+    list: &'a [Rule],
+    globs: GlobSet,
+}
+
+impl<'a> Rules<'a> {
+    pub fn new(options: &'a Options) -> Result<Self> {
+        let mut builder = GlobSetBuilder::new();
+
+        for rule in &options.rule {
+            let glob = Glob::new(&rule.pattern)
+                .wrap_err_with(|| format!("Invalid rule pattern: {}", rule.pattern))?;
+            builder.add(glob);
+        }
+
+        Ok(Self {
+            list: &options.rule,
+            globs: builder.build()?,
+        })
+    }
+
+    /// The rules that match the file, in the order that the user specified them.
+    /// The path is relative to the root directory of the file.
+    fn matching(&self, relative: &Path) -> impl Iterator<Item = &'a Rule> {
+        let list = self.list;
+
+        self.globs
+            .matches(relative)
+            .into_iter()
+            .map(move |index| &list[index])
+    }
+
+    /// Determine whether any matching rule leaves the file out of the comparison.
+    pub fn ignored(&self, relative: &Path) -> bool {
+        self.matching(relative).any(|rule| rule.ignore)
+    }
+
+    /// Apply the settings of the matching rules to the loaded file.
+    /// If several rules set the same setting, the last one applies.
+    pub fn apply(&self, file: &mut File, relative: &Path) {
+        for rule in self.matching(relative) {
+            if let Some(threshold) = rule.threshold {
+                file.threshold = threshold;
+            }
+            if let Some(metric) = rule.metric {
+                file.metric = metric;
+            }
+        }
+    }
+
+    /// The additional lines to skip in the file, on top of the global `--skip-lines` option.
+    pub fn skip_lines(&self, relative: &Path) -> impl Iterator<Item = &'a regex::Regex> {
+        self.matching(relative)
+            .flat_map(|rule| rule.skip_lines.iter())
+    }
+}
@@ -0,0 +1,288 @@
+/*
+Copyright 2022 Marek Suchánek <msuchane@redhat.com>
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Decide which files take part in the comparison.
+//!
+//! The options follow these precedence rules:
+//!
+//! 1. A file that matches any exclusion is skipped. The exclusions are `--exclude`,
+//!    `--ignore-file`, `--ignore-ext`, and the rules with the `ignore` setting.
+//! 2. If any inclusion is set, the file must match at least one of them.
+//!    The inclusions are `--include`, `--require-file`, and `--require-ext`.
+//! 3. Otherwise, the file is included.
+
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+use std::path::Path;
+
+use color_eyre::Result;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+
+use crate::rules::Rules;
+use crate::Options;
+
+/// Whether a file is part of the comparison, and why.
+pub enum Selection {
+    Included(Reason),
+    Skipped(Reason),
+}
+
+/// The option or the content that decided whether a file is part of the comparison.
+#[derive(Clone, Debug)]
+pub enum Reason {
+    NoFilters,
+    Include(String),
+    RequiredName,
+    RequiredExtension,
+    NotRequired,
+    Exclude(String),
+    IgnoredName,
+    IgnoredExtension,
+    IgnoredByRule,
+    /// The file contains binary data.
+    Binary,
+    TooLarge,
+    TooSmall,
+    TooFewLines,
+    TooFewWords,
+    /// The file contains invalid characters in this encoding.
+    NotDecodable(&'static str),
+    /// The archive is corrupt or truncated.
+    UnreadableArchive,
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoFilters => write!(f, "no filters"),
+            Self::Include(pattern) => write!(f, "matches --include {pattern}"),
+            Self::RequiredName => write!(f, "matches --require-file"),
+            Self::RequiredExtension => write!(f, "matches --require-ext"),
+            Self::NotRequired => {
+                write!(f, "matches no --include, --require-file, or --require-ext")
+            }
+            Self::Exclude(pattern) => write!(f, "matches --exclude {pattern}"),
+            Self::IgnoredName => write!(f, "matches --ignore-file"),
+            Self::IgnoredExtension => write!(f, "matches --ignore-ext"),
+            Self::IgnoredByRule => write!(f, "matches a rule with the ignore setting"),
+            Self::Binary => write!(f, "binary"),
+            Self::TooLarge => write!(f, "larger than --max-file-size"),
+            Self::TooSmall => write!(f, "smaller than --min-file-size"),
+            Self::TooFewLines => write!(f, "fewer lines than --min-lines"),
+            Self::TooFewWords => write!(f, "fewer words than --min-words"),
+            Self::NotDecodable(encoding) => write!(f, "not valid {encoding} text"),
+            Self::UnreadableArchive => write!(f, "unreadable archive"),
+        }
+    }
+}
+
+impl fmt::Display for Selection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Included(reason) => write!(f, "Included ({reason})"),
+            Self::Skipped(reason) => write!(f, "Skipped ({reason})"),
+        }
+    }
+}
+
+/// The compiled options that select the files.
+pub struct Selector<'a> {
+    options: &'a Options,
+    rules: &'a Rules<'a>,
+    include: Gitignore,
+    exclude: Gitignore,
+}
+
+impl<'a> Selector<'a> {
+    pub fn new(options: &'a Options, rules: &'a Rules<'a>) -> Result<Self> {
+        Ok(Self {
+            options,
+            rules,
+            include: patterns(&options.include)?,
+            exclude: patterns(&options.exclude)?,
+        })
+    }
+
+    /// Determine whether to include this file in the comparison or skip it,
+    /// based on the configured includes, requires, excludes, and ignores.
+    /// The patterns match the path relative to the root directory of the file.
+    pub fn select(&self, path: &Path, relative: &Path) -> Selection {
+        let options = self.options;
+        let name = path.file_name();
+        let extension = path.extension();
+
+        // The exclusions always win.
+        if let Some(reason) = self.excluded(path, relative) {
+            return Selection::Skipped(reason);
+        }
+
+        // Any inclusion is enough.
+        if let Some(pattern) = matched(&self.include, relative) {
+            return Selection::Included(Reason::Include(pattern));
+        }
+        if contains(&options.require_file, name) {
+            return Selection::Included(Reason::RequiredName);
+        }
+        if contains(&options.require_ext, extension) {
+            return Selection::Included(Reason::RequiredExtension);
+        }
+
+        if options.include.is_empty()
+            && options.require_file.is_empty()
+            && options.require_ext.is_empty()
+        {
+            Selection::Included(Reason::NoFilters)
+        } else {
+            Selection::Skipped(Reason::NotRequired)
+        }
+    }
+}
+
+impl Selector<'_> {
+    /// Determine whether any exclusion matches the file, regardless of the inclusions.
+    pub fn excluded(&self, path: &Path, relative: &Path) -> Option<Reason> {
+        let options = self.options;
+
+        if let Some(pattern) = matched(&self.exclude, relative) {
+            Some(Reason::Exclude(pattern))
+        } else if contains(&options.ignore_file, path.file_name()) {
+            Some(Reason::IgnoredName)
+        } else if contains(&options.ignore_ext, path.extension()) {
+            Some(Reason::IgnoredExtension)
+        } else if self.rules.ignored(relative) {
+            Some(Reason::IgnoredByRule)
+        } else {
+            None
+        }
+    }
+}
+
+/// If the file or any of its parent directories matches a pattern, return the pattern.
+fn matched(patterns: &Gitignore, relative: &Path) -> Option<String> {
+    if patterns.is_empty() {
+        return None;
+    }
+
+    // The parent directories can only be matched for paths under the root.
+    let matched = if relative.has_root() {
+        patterns.matched(relative, false)
+    } else {
+        patterns.matched_path_or_any_parents(relative, false)
+    };
+
+    // A negated pattern (`!`) cancels the match.
+    if matched.is_ignore() {
+        matched.inner().map(|glob| glob.original().to_string())
+    } else {
+        None
+    }
+}
+
+/// Compile the glob patterns in the gitignore syntax. They match paths
+/// relative to the root directory of each file.
+fn patterns(globs: &[String]) -> Result<Gitignore> {
+    let mut builder = GitignoreBuilder::new(".");
+
+    for glob in globs {
+        builder.add_line(None, glob)?;
+    }
+
+    Ok(builder.build()?)
+}
+
+/// Check whether the file name or extension is one of the listed ones.
+fn contains(list: &[OsString], item: Option<&OsStr>) -> bool {
+    item.is_some_and(|item| list.iter().any(|listed| listed == item))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Select each path with the options, and describe the decision.
+    fn decisions(options: &Options, paths: &[&str]) -> Vec<String> {
+        let rules = Rules::new(options).unwrap();
+        let selector = Selector::new(options, &rules).unwrap();
+
+        paths
+            .iter()
+            .map(|path| {
+                selector
+                    .select(Path::new(path), Path::new(path))
+                    .to_string()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn precedence() {
+        let os = |items: &[&str]| items.iter().map(OsString::from).collect::<Vec<_>>();
+
+        let cases = [
+            // The exclusion wins over the inclusion of the same file.
+            (
+                Options {
+                    include: vec!["docs/".to_string()],
+                    exclude: vec!["*.tmp.adoc".to_string()],
+                    ..Options::default()
+                },
+                [
+                    ("docs/a.adoc", "Included (matches --include docs/)"),
+                    ("docs/b.tmp.adoc", "Skipped (matches --exclude *.tmp.adoc)"),
+                    (
+                        "other/c.adoc",
+                        "Skipped (matches no --include, --require-file, or --require-ext)",
+                    ),
+                ],
+            ),
+            // An ignored name wins over a required extension.
+            (
+                Options {
+                    require_ext: os(&["adoc"]),
+                    ignore_file: os(&["attributes.adoc"]),
+                    ..Options::default()
+                },
+                [
+                    ("a.adoc", "Included (matches --require-ext)"),
+                    ("attributes.adoc", "Skipped (matches --ignore-file)"),
+                    (
+                        "image.png",
+                        "Skipped (matches no --include, --require-file, or --require-ext)",
+                    ),
+                ],
+            ),
+            // A negated pattern cancels the earlier exclusion, as in gitignore.
+            (
+                Options {
+                    exclude: vec!["drafts/".to_string(), "!drafts/keep.adoc".to_string()],
+                    ..Options::default()
+                },
+                [
+                    ("drafts/old.adoc", "Skipped (matches --exclude drafts/)"),
+                    ("drafts/keep.adoc", "Included (no filters)"),
+                    ("a.adoc", "Included (no filters)"),
+                ],
+            ),
+        ];
+
+        for (options, expected) in cases {
+            let paths: Vec<&str> = expected.iter().map(|(path, _)| *path).collect();
+            let reasons: Vec<&str> = expected.iter().map(|(_, reason)| *reason).collect();
+
+            assert_eq!(reasons, decisions(&options, &paths));
+        }
+    }
+}
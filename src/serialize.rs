@@ -14,55 +14,169 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
+use std::fmt::Write as _;
 use std::fs;
 use std::path::Path;
 
 use color_eyre::Result;
-use serde::Serialize;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
 
-use crate::Cli;
-use crate::Comparison;
+use crate::cli::Tokens;
+use crate::load_files::Skipped;
+use crate::results::{
+    labeled, Filters, Metadata, OutputComparison, OutputDetails, Results, SkippedFile,
+    SCHEMA_VERSION,
+};
+use crate::{Cli, Comparison, File, Options, Report};
 
-/// A record of a file comparison, formatted to be serialized for the user.
-#[derive(Serialize)]
-struct OutputComparison {
-    pct_similar: f64,
-    file1: String,
-    file2: String,
+/// The Markdown summary only lists this many of the most similar pairs,
+/// so that it fits into a merge request comment.
+const MARKDOWN_TOP_PAIRS: usize = 50;
+
+impl Metadata {
+    fn new(file_count: usize, options: &Options) -> Result<Self> {
+        // Present the file name filters as text, even if they aren't valid UTF-8.
+        let lossy = |names: &[std::ffi::OsString]| -> Vec<String> {
+            names
+                .iter()
+                .map(|name| name.to_string_lossy().into_owned())
+                .collect()
+        };
+
+        Ok(Self {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            timestamp: OffsetDateTime::now_utc().format(&Rfc3339)?,
+            threshold: options.threshold * 100.0,
+            metric: options.metric.name().to_string(),
+            tokens: (options.tokens != Tokens::Characters)
+                .then(|| options.tokens.name().to_string()),
+            filters: Filters {
+                ignore_file: lossy(&options.ignore_file),
+                ignore_ext: lossy(&options.ignore_ext),
+                require_file: lossy(&options.require_file),
+                require_ext: lossy(&options.require_ext),
+                include: options.include.clone(),
+                exclude: options.exclude.clone(),
+                skip_lines: options
+                    .skip_lines
+                    .iter()
+                    .map(|regex| regex.as_str().to_string())
+                    .collect(),
+                skip_block: options
+                    .skip_block
+                    .iter()
+                    .map(|block| block.definition.clone())
+                    .collect(),
+                replace: options.replace.iter().map(ToString::to_string).collect(),
+                normalize: options
+                    .normalize
+                    .iter()
+                    .map(|kind| kind.name().to_string())
+                    .collect(),
+            },
+            roots: options
+                .path
+                .iter()
+                .map(|root| root.display().to_string())
+                .collect(),
+            file_count,
+        })
+    }
 }
 
 impl OutputComparison {
     /// Convert from the internal `Comparison` format to the serializable `OutputComparison` format.
-    fn from_internal(comparison: &Comparison, options: &Cli) -> Result<Self> {
-        Ok(Self {
+    fn from_internal(comparison: &Comparison, options: &Options) -> Self {
+        let details = comparison.details.as_ref().map(|details| OutputDetails {
+            bytes1: comparison.file1.bytes,
+            bytes2: comparison.file2.bytes,
+            lines1: comparison.file1.lines,
+            lines2: comparison.file2.lines,
+            metric: comparison.metric.name().to_string(),
+            score: comparison.score,
+            trigram: comparison.trigram,
+            edit_distance: details.edit_distance,
+            lines_added: details.lines_added,
+            lines_removed: details.lines_removed,
+            identical: details.identical,
+        });
+
+        Self {
             pct_similar: comparison.similarity_pct.rounded(),
-            file1: stripped_path(comparison.path1, options)?,
-            file2: stripped_path(comparison.path2, options)?,
-        })
+            file1: stripped_path(comparison.file1, options),
+            file2: stripped_path(comparison.file2, options),
+            root1: root_label(comparison.file1.root, options),
+            root2: root_label(comparison.file2.root, options),
+            details,
+        }
     }
 }
 
-/// Serialize the resulting comparisons as a structured file.
-pub fn serialize(mut comparisons: Vec<Comparison>, options: &Cli) -> Result<()> {
-    log::debug!("Saving the comparison results…");
+impl SkippedFile {
+    fn from_internal(skipped: &Skipped, options: &Options) -> Self {
+        let relative = skipped
+            .path
+            .strip_prefix(&options.path[skipped.root])
+            .unwrap_or(&skipped.path);
 
-    // Sort from highest to lowest. You can't sort f64 values, so convert them to u32
-    // with a precision of percentage with a single decimal place, then subtract from 1000.
-    comparisons.sort_unstable_by_key(|comparison| {
-        1000 - (comparison.similarity_pct.0 * 10.0).round() as i32
-    });
+        Self {
+            file: relative.display().to_string(),
+            root: root_label(skipped.root, options),
+            reason: skipped.reason.to_string(),
+        }
+    }
+}
+
+/// Convert the resulting comparisons to the owned, serializable results.
+/// The comparisons are already sorted from the most similar.
+pub fn build_results(
+    comparisons: &[Comparison],
+    file_count: usize,
+    skipped: &[Skipped],
+    options: &Options,
+) -> Result<Results> {
+    Ok(Results {
+        schema_version: SCHEMA_VERSION,
+        metadata: Some(Metadata::new(file_count, options)?),
+        comparisons: comparisons
+            .iter()
+            .map(|comparison| OutputComparison::from_internal(comparison, options))
+            .collect(),
+        skipped: skipped
+            .iter()
+            .map(|skipped| SkippedFile::from_internal(skipped, options))
+            .collect(),
+    })
+}
 
-    let output_comparisons: Vec<OutputComparison> = comparisons
-        .into_iter()
-        .map(|comparison| OutputComparison::from_internal(&comparison, options))
-        .collect::<Result<Vec<_>>>()?;
+/// Serialize the report as structured files.
+pub fn serialize(report: &Report, cli: &Cli, options: &Options) -> Result<()> {
+    log::debug!("Saving the comparison results…");
+
+    let comparisons = &report.results.comparisons;
 
-    // Serialize to CSV, JSON, or both, depending on the command-line options.
-    if let Some(path) = &options.csv {
-        as_csv(&output_comparisons, path)?;
+    // Serialize to any combination of the formats, depending on the command-line options.
+    if let Some(path) = &cli.csv {
+        as_csv(comparisons, path)?;
+    }
+    if let Some(path) = &cli.json {
+        // Only list the skipped files on request, because they can be many.
+        if cli.list_skipped {
+            as_json(&report.results, path)?;
+        } else {
+            let results = Results {
+                skipped: Vec::new(),
+                ..report.results.clone()
+            };
+            as_json(&results, path)?;
+        }
+    }
+    if let Some(path) = &cli.markdown {
+        fs::write(path, markdown(comparisons, &report.clusters, options)?)?;
     }
-    if let Some(path) = &options.json {
-        as_json(&output_comparisons, path)?;
+    if let Some(path) = &cli.junit {
+        fs::write(path, junit(comparisons)?)?;
     }
 
     Ok(())
@@ -73,17 +187,57 @@ fn as_csv(comparisons: &[OutputComparison], file: &Path) -> Result<()> {
     // Prepare to write to the CSV file.
     let mut wtr = csv::Writer::from_path(file)?;
 
+    // The details are either present in all comparisons, or in none.
+    let with_details = comparisons
+        .first()
+        .is_some_and(|comparison| comparison.details.is_some());
+
     // The CSV header:
-    wtr.write_record(["% similar", "File 1", "File 2"])?;
+    let mut header = vec!["% similar", "File 1", "File 2"];
+    if with_details {
+        header.extend([
+            "Bytes 1",
+            "Bytes 2",
+            "Lines 1",
+            "Lines 2",
+            "Metric",
+            "Score",
+            "Trigram",
+            "Edit distance",
+            "Lines added",
+            "Lines removed",
+            "Identical",
+        ]);
+    }
+    wtr.write_record(header)?;
 
     // Each comparison entry writes a row in the CSV table.
     for comparison in comparisons {
-        wtr.write_record([
+        let mut record = vec![
             // For prettier alignment, always include one decimal, even if it's .0
-            &format!("{:.1}", &comparison.pct_similar),
-            &comparison.file1,
-            &comparison.file2,
-        ])?;
+            format!("{:.1}", &comparison.pct_similar),
+            comparison.labeled_file1(),
+            comparison.labeled_file2(),
+        ];
+        if let Some(details) = &comparison.details {
+            record.extend([
+                details.bytes1.to_string(),
+                details.bytes2.to_string(),
+                details.lines1.to_string(),
+                details.lines2.to_string(),
+                details.metric.clone(),
+                format!("{:.4}", details.score),
+                format!("{:.4}", details.trigram),
+                details
+                    .edit_distance
+                    .map(|distance| distance.to_string())
+                    .unwrap_or_default(),
+                details.lines_added.to_string(),
+                details.lines_removed.to_string(),
+                details.identical.to_string(),
+            ]);
+        }
+        wtr.write_record(record)?;
     }
 
     // Flush the CSV writer buffer.
@@ -93,18 +247,265 @@ fn as_csv(comparisons: &[OutputComparison], file: &Path) -> Result<()> {
 }
 
 /// Serialize and save the comparisons as a pretty-formatted JSON file.
-fn as_json(comparisons: &[OutputComparison], file: &Path) -> Result<()> {
+fn as_json(results: &Results, file: &Path) -> Result<()> {
     // Write directly to the file so that we don't hold the whole JSON text in memory.
     let out_file = fs::File::create(file)?;
-    serde_json::to_writer_pretty(out_file, comparisons)?;
+    serde_json::to_writer_pretty(out_file, results)?;
 
     Ok(())
 }
 
+/// Format the comparisons as a Markdown summary with a table of the most similar
+/// pairs and a list of clusters of similar files.
+fn markdown(
+    comparisons: &[OutputComparison],
+    clusters: &[Vec<String>],
+    options: &Options,
+) -> Result<String> {
+    let mut md = String::new();
+
+    writeln!(md, "# Similar files\n")?;
+    writeln!(
+        md,
+        "Found {} pairs of files that are more than {:.1}% similar.\n",
+        comparisons.len(),
+        options.threshold * 100.0
+    )?;
+
+    if !comparisons.is_empty() {
+        writeln!(md, "## Most similar pairs\n")?;
+        writeln!(md, "| % similar | File 1 | File 2 |")?;
+        writeln!(md, "|----------:|--------|--------|")?;
+
+        for comparison in comparisons.iter().take(MARKDOWN_TOP_PAIRS) {
+            writeln!(
+                md,
+                "| {:.1} | {} | {} |",
+                comparison.pct_similar,
+                md_code(&comparison.labeled_file1()),
+                md_code(&comparison.labeled_file2())
+            )?;
+        }
+
+        if comparisons.len() > MARKDOWN_TOP_PAIRS {
+            writeln!(
+                md,
+                "\n…and {} more pairs.",
+                comparisons.len() - MARKDOWN_TOP_PAIRS
+            )?;
+        }
+
+        writeln!(md, "\n## Clusters of similar files\n")?;
+
+        for (index, cluster) in clusters.iter().enumerate() {
+            // The comparisons are sorted, so the first match is the highest similarity.
+            let highest = comparisons
+                .iter()
+                .find(|comparison| cluster.contains(&comparison.labeled_file1()))
+                .map_or(0.0, |comparison| comparison.pct_similar);
+
+            writeln!(
+                md,
+                "{}. {} files, up to {:.1}% similar:",
+                index + 1,
+                cluster.len(),
+                highest
+            )?;
+            for path in cluster {
+                writeln!(md, "    * {}", md_code(path))?;
+            }
+        }
+    }
+
+    Ok(md)
+}
+
+/// Format the comparisons as a JUnit XML report, where each similar pair
+/// is a failing test case.
+fn junit(comparisons: &[OutputComparison]) -> Result<String> {
+    let mut xml = String::new();
+    let count = comparisons.len();
+
+    writeln!(xml, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        xml,
+        r#"<testsuites name="near-facsimile" tests="{count}" failures="{count}">"#
+    )?;
+    writeln!(
+        xml,
+        r#"  <testsuite name="near-facsimile" tests="{count}" failures="{count}">"#
+    )?;
+
+    for comparison in comparisons {
+        let file1 = xml_escape(&comparison.labeled_file1());
+        let file2 = xml_escape(&comparison.labeled_file2());
+        let kind = if comparison.pct_similar >= 100.0 {
+            "identical"
+        } else {
+            "similar"
+        };
+
+        writeln!(
+            xml,
+            r#"    <testcase classname="{file1}" name="{file1} ↔ {file2}">"#
+        )?;
+        writeln!(
+            xml,
+            r#"      <failure type="{kind}" message="These two files are {kind} ({:.1}%)">{file1}"#,
+            comparison.pct_similar
+        )?;
+        writeln!(xml, "{file2}</failure>")?;
+        writeln!(xml, "    </testcase>")?;
+    }
+
+    writeln!(xml, "  </testsuite>")?;
+    writeln!(xml, "</testsuites>")?;
+
+    Ok(xml)
+}
+
+/// Format the text as inline Markdown code that's safe to use in a table cell.
+/// If the text contains backticks, the code span uses a longer run of backticks
+/// than any inside the text.
+fn md_code(text: &str) -> String {
+    let text = text.replace('|', "\\|");
+    let longest_run = text
+        .split(|character| character != '`')
+        .map(str::len)
+        .max()
+        .unwrap_or(0);
+
+    if longest_run == 0 {
+        format!("`{text}`")
+    } else {
+        // The spaces keep a backtick at the edge of the text apart from the delimiters.
+        let delimiter = "`".repeat(longest_run + 1);
+        format!("{delimiter} {text} {delimiter}")
+    }
+}
+
+/// Escape the characters that have a special meaning in XML text and attributes.
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
 /// Present the file path without the common, shared prefix
-/// of the root comparison directory.
-fn stripped_path(path: &Path, options: &Cli) -> Result<String> {
-    let stripped = path.strip_prefix(&options.path)?;
+/// of its root comparison directory.
+fn stripped_path(file: &File, options: &Options) -> String {
+    file.relative_path(options).display().to_string()
+}
+
+/// Label the root directory of a file, if the comparison spans several of them.
+fn root_label(root: usize, options: &Options) -> Option<String> {
+    (options.path.len() > 1).then(|| options.path[root].display().to_string())
+}
+
+/// Present the file path relative to its root directory, with the root label
+/// if the comparison spans several root directories.
+pub fn display_path(file: &File, options: &Options) -> String {
+    labeled(
+        root_label(file.root, options).as_deref(),
+        &stripped_path(file, options),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    Ok(stripped.display().to_string())
+    fn comparison(pct_similar: f64, file1: &str, file2: &str) -> OutputComparison {
+        OutputComparison {
+            pct_similar,
+            file1: file1.to_string(),
+            file2: file2.to_string(),
+            root1: None,
+            root2: None,
+            details: None,
+        }
+    }
+
+    #[test]
+    fn markdown_summary() {
+        let comparisons = [
+            comparison(100.0, "a.adoc", "b|c.adoc"),
+            comparison(90.0, "b|c.adoc", "d`e.adoc"),
+        ];
+        let clusters = [vec![
+            "a.adoc".to_string(),
+            "b|c.adoc".to_string(),
+            "d`e.adoc".to_string(),
+        ]];
+
+        let md = markdown(&comparisons, &clusters, &Options::default()).unwrap();
+
+        assert!(md.contains("Found 2 pairs of files that are more than 85.0% similar."));
+        assert!(md.contains("| 100.0 | `a.adoc` | `b\\|c.adoc` |"));
+        assert!(md.contains("| 90.0 | `b\\|c.adoc` | `` d`e.adoc `` |"));
+        assert!(md.contains("1. 3 files, up to 100.0% similar:\n    * `a.adoc`\n"));
+    }
+
+    #[test]
+    fn markdown_code_spans() {
+        assert_eq!("`plain.md`", md_code("plain.md"));
+        assert_eq!("`` a`b ``", md_code("a`b"));
+        assert_eq!("``` ``a`` ```", md_code("``a``"));
+    }
+
+    #[test]
+    fn junit_report() {
+        let comparisons = [
+            comparison(100.0, "a.adoc", "b.adoc"),
+            comparison(90.0, "<c>.adoc", "d&e.adoc"),
+        ];
+
+        let xml = junit(&comparisons).unwrap();
+
+        assert!(xml.contains(r#"<testsuite name="near-facsimile" tests="2" failures="2">"#));
+        assert!(xml.contains(
+            r#"<failure type="identical" message="These two files are identical (100.0%)">a.adoc"#
+        ));
+        assert!(xml.contains(
+            r#"<testcase classname="&lt;c&gt;.adoc" name="&lt;c&gt;.adoc ↔ d&amp;e.adoc">"#
+        ));
+        assert!(xml
+            .contains(r#"<failure type="similar" message="These two files are similar (90.0%)">"#));
+    }
+
+    #[test]
+    fn root_labels() {
+        let file = File {
+            root: 1,
+            ..File::with_content("new/intro.adoc", "")
+        };
+
+        // The label only appears if the comparison spans several root directories.
+        let options = Options {
+            path: vec!["old".into(), "new".into()],
+            ..Options::default()
+        };
+        assert_eq!("new:intro.adoc", display_path(&file, &options));
+
+        let options = Options {
+            path: vec!["unused".into(), "new".into()],
+            ..Options::default()
+        };
+        let comparison = OutputComparison {
+            root1: root_label(0, &options),
+            root2: root_label(1, &options),
+            ..comparison(90.0, "a.adoc", "b.adoc")
+        };
+        assert_eq!("unused:a.adoc", comparison.labeled_file1());
+        assert_eq!("new:b.adoc", comparison.labeled_file2());
+
+        let single = File::with_content("new/intro.adoc", "");
+        let options = Options {
+            path: vec!["new".into()],
+            ..Options::default()
+        };
+        assert_eq!("intro.adoc", display_path(&single, &options));
+    }
 }
@@ -0,0 +1,218 @@
+/*
+Copyright 2022 Marek Suchánek <msuchane@redhat.com>
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! The TF-IDF cosine similarity, which discounts the terms that most files share,
+//! such as standard phrasing and boilerplate.
+
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+
+use crate::cli::Metric;
+use crate::File;
+
+/// Weigh the terms of each file by their frequency in the file, and by their rarity
+/// in the corpus. A term that appears in every file weighs the least, but still counts,
+/// so that files that only share such terms can be identical.
+/// The weights of each file form a vector of unit length, sorted by the term.
+/// Only calculated if any file uses the TF-IDF metric.
+// The counts are far below the precision limit of `f64`.
+#[allow(clippy::cast_precision_loss)]
+pub fn weigh_files(files: &mut [File]) {
+    if !files.iter().any(|file| file.metric == Metric::Tfidf) {
+        return;
+    }
+
+    let counts: Vec<BTreeMap<u32, usize>> = files
+        .iter()
+        .map(|file| {
+            let mut counts = BTreeMap::new();
+            for &term in &file.tokens {
+                *counts.entry(term).or_insert(0) += 1;
+            }
+            counts
+        })
+        .collect();
+
+    // The number of files that contain each term.
+    let mut document_frequency: HashMap<u32, usize> = HashMap::new();
+    for term in counts.iter().flat_map(BTreeMap::keys) {
+        *document_frequency.entry(*term).or_insert(0) += 1;
+    }
+
+    let file_count = files.len() as f64;
+
+    for (file, counts) in files.iter_mut().zip(counts) {
+        let mut weights: Vec<(u32, f64)> = counts
+            .into_iter()
+            .map(|(term, count)| {
+                let frequency = 1.0 + (count as f64).ln();
+                // The smoothed inverse document frequency is at least 1.
+                let rarity =
+                    ((1.0 + file_count) / (1.0 + document_frequency[&term] as f64)).ln() + 1.0;
+                (term, frequency * rarity)
+            })
+            .collect();
+
+        let length = weights
+            .iter()
+            .map(|(_, weight)| weight * weight)
+            .sum::<f64>()
+            .sqrt();
+        for (_, weight) in &mut weights {
+            *weight /= length;
+        }
+
+        file.weights = weights;
+    }
+}
+
+/// The cosine similarity of the weight vectors of two files.
+pub fn cosine(weights1: &[(u32, f64)], weights2: &[(u32, f64)]) -> f64 {
+    let mut product = 0.0;
+    let (mut index1, mut index2) = (0, 0);
+
+    while let (Some((term1, weight1)), Some((term2, weight2))) =
+        (weights1.get(index1), weights2.get(index2))
+    {
+        match term1.cmp(term2) {
+            Ordering::Less => index1 += 1,
+            Ordering::Greater => index2 += 1,
+            Ordering::Equal => {
+                product += weight1 * weight2;
+                index1 += 1;
+                index2 += 1;
+            }
+        }
+    }
+
+    // Rounding errors can push identical files slightly above 1.0.
+    product.min(1.0)
+}
+
+/// The share of files above which a term is too common to find the related files by it.
+const COMMON_TERM_SHARE: f64 = 0.5;
+
+/// The pairs of files that can be similar enough, as indexes into the files.
+///
+/// The inverted index from the rarer terms to files finds the pairs that share any of them,
+/// without comparing all pairs. The common terms would relate almost every pair, so they
+/// aren't in the index. The pairs that only share common terms are still candidates
+/// if the common terms alone could make them as similar as the lowest threshold.
+// The file counts are far below the precision limit of `f64`.
+#[allow(clippy::cast_precision_loss)]
+pub fn candidates(files: &[File]) -> Vec<(usize, usize)> {
+    let mut document_frequency: HashMap<u32, usize> = HashMap::new();
+    for (term, _) in files.iter().flat_map(|file| &file.weights) {
+        *document_frequency.entry(*term).or_insert(0) += 1;
+    }
+    let common_limit = files.len() as f64 * COMMON_TERM_SHARE;
+    let is_common = |term: &u32| document_frequency[term] as f64 > common_limit;
+
+    let threshold = files
+        .iter()
+        .map(|file| file.threshold)
+        .fold(f64::INFINITY, f64::min);
+
+    let mut index: HashMap<u32, Vec<usize>> = HashMap::new();
+    // The files whose common terms alone could reach the threshold.
+    let mut common_heavy: Vec<usize> = Vec::new();
+    let mut pairs = Vec::new();
+
+    for (current, file) in files.iter().enumerate() {
+        // By the Cauchy-Schwarz inequality, the cosine of two files that only share
+        // common terms is at most the product of the lengths of their common parts.
+        // Both lengths must reach the threshold, because neither exceeds 1.
+        let common_length = file
+            .weights
+            .iter()
+            .filter(|(term, _)| is_common(term))
+            .map(|(_, weight)| weight * weight)
+            .sum::<f64>()
+            .sqrt();
+        // Allow for the rounding errors of identical files.
+        if common_length + 1e-9 >= threshold {
+            pairs.extend(common_heavy.iter().map(|&earlier| (earlier, current)));
+            common_heavy.push(current);
+        }
+
+        // The index only contains the earlier files, so each pair occurs once.
+        let rare_terms = file
+            .weights
+            .iter()
+            .map(|(term, _)| term)
+            .filter(|term| !is_common(term));
+        let mut related: Vec<usize> = rare_terms
+            .clone()
+            .filter_map(|term| index.get(term))
+            .flatten()
+            .copied()
+            .collect();
+        related.sort_unstable();
+        related.dedup();
+
+        pairs.extend(related.into_iter().map(|earlier| (earlier, current)));
+
+        for term in rare_terms {
+            index.entry(*term).or_default().push(current);
+        }
+    }
+
+    pairs.sort_unstable_by_key(|&(earlier, current)| (current, earlier));
+    pairs.dedup();
+
+    pairs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(tokens: Vec<u32>) -> File {
+        File {
+            metric: Metric::Tfidf,
+            tokens,
+            ..File::with_content("", "")
+        }
+    }
+
+    #[test]
+    fn shared_terms_weigh_less() {
+        // Term 0 is boilerplate that every file contains.
+        let mut files = vec![
+            file(vec![0, 1, 2]),
+            file(vec![0, 1, 2, 2]),
+            file(vec![0, 3]),
+            file(vec![0, 4]),
+        ];
+        weigh_files(&mut files);
+
+        assert!(files[2].weights[0].1 < files[2].weights[1].1);
+        assert!(cosine(&files[0].weights, &files[1].weights) > 0.9);
+        assert!(cosine(&files[0].weights, &files[2].weights) < 0.25);
+        // The pairs that only share the common term can't reach the threshold.
+        assert_eq!(vec![(0, 1)], candidates(&files));
+    }
+
+    #[test]
+    fn identical_files() {
+        // All the terms appear in every file.
+        let mut files = vec![file(vec![0, 1, 1, 2]), file(vec![0, 1, 1, 2])];
+        weigh_files(&mut files);
+
+        assert!(cosine(&files[0].weights, &files[1].weights) > 0.999_999);
+        assert_eq!(vec![(0, 1)], candidates(&files));
+    }
+}
@@ -0,0 +1,173 @@
+/*
+Copyright 2022 Marek Suchánek <msuchane@redhat.com>
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Split the compared content into tokens, such as words, for the token-level comparison.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+
+use color_eyre::eyre::WrapErr;
+use color_eyre::Result;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::cli::{Metric, Tokens};
+use crate::{File, Options};
+
+/// Assigns each distinct token in the corpus a number, so that the metrics
+/// compare numbers rather than strings.
+#[derive(Default)]
+struct Interner {
+    ids: HashMap<String, u32>,
+}
+
+impl Interner {
+    fn id(&mut self, token: &str) -> u32 {
+        if let Some(&id) = self.ids.get(token) {
+            return id;
+        }
+        let id = u32::try_from(self.ids.len()).expect("Too many distinct tokens in the files.");
+        self.ids.insert(token.to_string(), id);
+        id
+    }
+}
+
+/// Split the content of each file into tokens, without the stopwords.
+/// In the character mode, the files stay as they are, unless the TF-IDF metric
+/// needs their words.
+pub fn tokenize_files(files: &mut [File], options: &Options) -> Result<()> {
+    let tfidf = files.iter().any(|file| file.metric == Metric::Tfidf);
+    let mode = match options.tokens {
+        Tokens::Characters if tfidf => Tokens::Words,
+        Tokens::Characters => return Ok(()),
+        mode => mode,
+    };
+
+    let stopwords = stopwords(options)?;
+    let mut interner = Interner::default();
+
+    for file in files {
+        file.tokens = split(&file.content, mode)
+            .filter(|token| !stopwords.contains(&token.to_lowercase()))
+            .map(|token| interner.id(token))
+            .collect();
+    }
+
+    log::debug!("Number of distinct tokens: {}", interner.ids.len());
+
+    Ok(())
+}
+
+/// Split the text into tokens: at whitespace, or at the Unicode word boundaries,
+/// without the punctuation.
+pub fn split(text: &str, mode: Tokens) -> Box<dyn Iterator<Item = &str> + '_> {
+    match mode {
+        Tokens::Characters | Tokens::Whitespace => Box::new(text.split_whitespace()),
+        Tokens::Words => Box::new(text.unicode_words()),
+    }
+}
+
+/// Load the stopwords from the `--stopwords` file, one on each line, in lowercase.
+fn stopwords(options: &Options) -> Result<HashSet<String>> {
+    let Some(file) = &options.stopwords else {
+        return Ok(HashSet::new());
+    };
+
+    let text = fs::read_to_string(file)
+        .wrap_err_with(|| format!("Failed to read the stopwords file: {}", file.display()))?;
+
+    Ok(text
+        .lines()
+        .map(str::trim)
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect())
+}
+
+/// The normalized Levenshtein similarity of two token sequences.
+// The generic metrics of `strsim` require a sized collection rather than a slice.
+// The token counts are far below the precision limit of `f64`.
+#[allow(clippy::ptr_arg, clippy::cast_precision_loss)]
+pub fn levenshtein(tokens1: &Vec<u32>, tokens2: &Vec<u32>) -> f64 {
+    let longest = tokens1.len().max(tokens2.len());
+    if longest == 0 {
+        return 1.0;
+    }
+    let distance = strsim::generic_levenshtein(tokens1, tokens2);
+
+    1.0 - distance as f64 / longest as f64
+}
+
+/// The Jaro similarity of two token sequences.
+#[allow(clippy::ptr_arg)]
+pub fn jaro(tokens1: &Vec<u32>, tokens2: &Vec<u32>) -> f64 {
+    strsim::generic_jaro(tokens1, tokens2)
+}
+
+/// The share of the distinct token trigrams that the two sequences have in common.
+/// Sequences shorter than three tokens count as a single trigram.
+#[allow(clippy::cast_precision_loss)]
+pub fn trigram(tokens1: &[u32], tokens2: &[u32]) -> f64 {
+    let trigrams = |tokens: &[u32]| -> HashSet<Vec<u32>> {
+        if tokens.len() < 3 {
+            HashSet::from([tokens.to_vec()])
+        } else {
+            tokens.windows(3).map(<[u32]>::to_vec).collect()
+        }
+    };
+
+    let trigrams1 = trigrams(tokens1);
+    let trigrams2 = trigrams(tokens2);
+    let shared = trigrams1.intersection(&trigrams2).count();
+    let all = trigrams1.union(&trigrams2).count();
+
+    shared as f64 / all as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn token_metrics() {
+        let words: Vec<&str> = split(
+            "Install the “near-facsimile” tool, then run it.",
+            Tokens::Words,
+        )
+        .collect();
+        assert_eq!(
+            vec![
+                "Install",
+                "the",
+                "near",
+                "facsimile",
+                "tool",
+                "then",
+                "run",
+                "it"
+            ],
+            words
+        );
+
+        // One changed long word is a single edit.
+        let original = vec![1, 2, 3, 4];
+        let changed = vec![1, 2, 5, 4];
+        assert!((levenshtein(&original, &changed) - 0.75).abs() < f64::EPSILON);
+        assert!((levenshtein(&Vec::new(), &Vec::new()) - 1.0).abs() < f64::EPSILON);
+        assert!((trigram(&original, &original) - 1.0).abs() < f64::EPSILON);
+        assert!(trigram(&original, &changed) < f64::EPSILON);
+        assert!(jaro(&original, &changed) > 0.8);
+    }
+}